@@ -0,0 +1,34 @@
+// Compares the scanline and edge-function triangle traversals on the built-in scenes.
+// Run with `cargo bench --bench rasterizer`.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use cgfs_with_wgpu::{Traversal, init_rasterizer_with_canvas, init_renderer};
+
+const TRAVERSALS: [Traversal; 2] = [Traversal::Scanline, Traversal::EdgeFunction];
+
+fn textured_boxes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("textured_boxes");
+    for canvas in [[400, 400], [1600, 1600]] {
+        for traversal in TRAVERSALS {
+            let mut rasterizer = init_rasterizer_with_canvas(canvas);
+            rasterizer.set_traversal(traversal);
+            let id = BenchmarkId::new(format!("{:?}", traversal), canvas[0]);
+            group.bench_function(id, |b| b.iter(|| rasterizer.pass()));
+        }
+    }
+    group.finish();
+}
+
+fn shaded_viewer_scene(c: &mut Criterion) {
+    let mut group = c.benchmark_group("shaded_viewer_scene");
+    for traversal in TRAVERSALS {
+        let mut renderer = init_renderer();
+        if renderer.get_traversal() != traversal {
+            renderer.toggle_traversal();
+        }
+        group.bench_function(format!("{:?}", traversal), |b| b.iter(|| renderer.pass()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, textured_boxes, shaded_viewer_scene);
+criterion_main!(benches);
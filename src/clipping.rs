@@ -0,0 +1,191 @@
+// Clipping in canvas space against an axis aligned rectangle given as [min corner, max corner]
+
+// Outer edges of the outermost pixels of a canvas centered on the origin, pixel centers sit on whole numbers
+pub fn canvas_bounds(canvas: [i32; 2]) -> [[f32; 2]; 2] {
+    let [half_width, half_height] = [canvas[0] / 2, canvas[1] / 2].map(|half| half as f32);
+    return [[-half_width - 0.5, -half_height - 0.5], [half_width - 0.5, half_height - 0.5]];
+}
+
+// Liang–Barsky: the segment is a + t * (b - a), every side of the rectangle narrows the range of t.
+// Returns the part of the segment inside the rectangle, None when it misses it entirely
+pub fn clip_line(a: [f32; 2], b: [f32; 2], bounds: [[f32; 2]; 2]) -> Option<([f32; 2], [f32; 2])> {
+    let [min, max] = bounds;
+    let delta = [b[0] - a[0], b[1] - a[1]];
+    let mut t_enter: f32 = 0.0;
+    let mut t_exit: f32 = 1.0;
+
+    // p is the direction against the side's normal, q how far a is inside that side
+    let sides = [
+        (-delta[0], a[0] - min[0]),
+        (delta[0], max[0] - a[0]),
+        (-delta[1], a[1] - min[1]),
+        (delta[1], max[1] - a[1]),
+    ];
+    for (p, q) in sides {
+        if p == 0.0 {
+            // Parallel to this side, either fully inside or fully outside it
+            if q < 0.0 { return None; }
+            continue;
+        }
+        let t = q / p;
+        if p < 0.0 {
+            t_enter = t_enter.max(t);
+        } else {
+            t_exit = t_exit.min(t);
+        }
+        if t_enter > t_exit { return None; }
+    }
+
+    let point_at = |t: f32| [a[0] + delta[0] * t, a[1] + delta[1] * t];
+    let start = if t_enter > 0.0 { point_at(t_enter) } else { a };
+    let end = if t_exit < 1.0 { point_at(t_exit) } else { b };
+    return Some((start, end));
+}
+
+// Sutherland–Hodgman: the polygon is clipped against one side of the rectangle at a time.
+// The result keeps the winding of the input and is empty when nothing is left inside
+pub fn clip_polygon(points: &[[f32; 2]], bounds: [[f32; 2]; 2]) -> Vec<[f32; 2]> {
+    let [min, max] = bounds;
+    let mut polygon = points.to_vec();
+
+    for (axis, limit, keep_above) in [(0, min[0], true), (0, max[0], false), (1, min[1], true), (1, max[1], false)] {
+        if polygon.is_empty() { break; }
+        polygon = clip_against_side(&polygon, axis, limit, keep_above);
+    }
+
+    return polygon;
+}
+
+fn clip_against_side(polygon: &[[f32; 2]], axis: usize, limit: f32, keep_above: bool) -> Vec<[f32; 2]> {
+    let inside = |point: [f32; 2]| if keep_above { point[axis] >= limit } else { point[axis] <= limit };
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+
+    for (idx, &current) in polygon.iter().enumerate() {
+        let previous = polygon[(idx + polygon.len() - 1) % polygon.len()];
+        match (inside(previous), inside(current)) {
+            (true, true) => clipped.push(current),
+            (true, false) => clipped.push(crossing(previous, current, axis, limit)),
+            (false, true) => {
+                clipped.push(crossing(previous, current, axis, limit));
+                clipped.push(current);
+            },
+            (false, false) => {},
+        }
+    }
+
+    return clipped;
+}
+
+// Where the edge p-q meets the side. The ends are put in a fixed order first, so an edge shared by two
+// polygons, walked in opposite directions, is cut at exactly the same point in both
+fn crossing(p: [f32; 2], q: [f32; 2], axis: usize, limit: f32) -> [f32; 2] {
+    let (p, q) = if (p[0], p[1]) <= (q[0], q[1]) { (p, q) } else { (q, p) };
+    let other = 1 - axis;
+    let t = (limit - p[axis]) / (q[axis] - p[axis]);
+
+    let mut point = [0.0; 2];
+    point[axis] = limit;
+    point[other] = p[other] + (q[other] - p[other]) * t;
+    return point;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const BOUNDS: [[f32; 2]; 2] = [[-20.0, -10.0], [30.0, 15.0]];
+    const EPSILON: f32 = 1e-3;
+
+    fn inside(point: [f32; 2], margin: f32) -> bool {
+        let [min, max] = BOUNDS;
+        return point[0] >= min[0] - margin && point[0] <= max[0] + margin && point[1] >= min[1] - margin && point[1] <= max[1] + margin;
+    }
+
+    fn point() -> impl Strategy<Value = [f32; 2]> {
+        [-100.0f32..100.0, -100.0f32..100.0]
+    }
+
+    // Twice the signed area, positive for counter-clockwise polygons
+    fn signed_area(polygon: &[[f32; 2]]) -> f32 {
+        return (0 .. polygon.len())
+            .map(|idx| {
+                let [p, q] = [polygon[idx], polygon[(idx + 1) % polygon.len()]];
+                p[0] * q[1] - q[0] * p[1]
+            })
+            .sum();
+    }
+
+    // Inside or on the boundary of a convex polygon of either winding
+    fn contains(polygon: &[[f32; 2]], point: [f32; 2], margin: f32) -> bool {
+        let orientation = signed_area(polygon).signum();
+        return (0 .. polygon.len()).all(|idx| {
+            let [p, q] = [polygon[idx], polygon[(idx + 1) % polygon.len()]];
+            let length = ((q[0] - p[0]).powi(2) + (q[1] - p[1]).powi(2)).sqrt();
+            let cross = (q[0] - p[0]) * (point[1] - p[1]) - (q[1] - p[1]) * (point[0] - p[0]);
+            cross * orientation >= -margin * length
+        });
+    }
+
+    proptest! {
+        // A point of the segment lies inside the rectangle exactly when it lies on the clipped part
+        #[test]
+        fn clipped_lines_keep_the_inside_of_the_segment(a in point(), b in point(), t in 0.0f32..=1.0) {
+            let sample = [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t];
+
+            match clip_line(a, b, BOUNDS) {
+                Some((start, end)) => {
+                    prop_assert!(inside(start, EPSILON) && inside(end, EPSILON));
+                    let on_clipped = (sample[0] - start[0]) * (sample[0] - end[0]) <= EPSILON
+                        && (sample[1] - start[1]) * (sample[1] - end[1]) <= EPSILON;
+                    if inside(sample, -EPSILON) { prop_assert!(on_clipped); }
+                    if on_clipped { prop_assert!(inside(sample, EPSILON)); }
+                },
+                None => prop_assert!(!inside(sample, -EPSILON)),
+            }
+        }
+
+        #[test]
+        fn clipped_triangles_are_the_overlap_with_the_rectangle(a in point(), b in point(), c in point(), sample in point()) {
+            let triangle = [a, b, c];
+            prop_assume!(signed_area(&triangle).abs() > 1.0);
+            let clipped = clip_polygon(&triangle, BOUNDS);
+
+            prop_assert!(clipped.iter().all(|&vertex| inside(vertex, EPSILON)));
+            if clipped.len() < 3 {
+                prop_assert!(!(contains(&triangle, sample, -EPSILON) && inside(sample, -EPSILON)));
+                return Ok(());
+            }
+            prop_assert!(signed_area(&clipped) * signed_area(&triangle) >= 0.0);
+            if contains(&triangle, sample, -EPSILON) && inside(sample, -EPSILON) {
+                prop_assert!(contains(&clipped, sample, EPSILON));
+            }
+            if contains(&clipped, sample, -EPSILON) {
+                prop_assert!(contains(&triangle, sample, EPSILON) && inside(sample, EPSILON));
+            }
+        }
+    }
+
+    #[test]
+    fn shapes_inside_the_rectangle_are_unchanged() {
+        assert_eq!(clip_line([-5.0, 2.0], [10.0, -3.0], BOUNDS), Some(([-5.0, 2.0], [10.0, -3.0])));
+        let triangle = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]];
+        assert_eq!(clip_polygon(&triangle, BOUNDS), triangle.to_vec());
+    }
+
+    #[test]
+    fn shapes_outside_the_rectangle_are_removed() {
+        assert_eq!(clip_line([-50.0, 20.0], [-25.0, 40.0], BOUNDS), None);
+        assert!(clip_polygon(&[[40.0, 0.0], [50.0, 0.0], [40.0, 10.0]], BOUNDS).is_empty());
+    }
+
+    // An edge shared by two triangles is walked in opposite directions but must be cut at the same point
+    #[test]
+    fn shared_edges_are_cut_at_the_same_point() {
+        let [a, b] = [[-43.7, -3.3], [12.9, 8.1]];
+        let first = clip_polygon(&[a, b, [0.0, -9.0]], BOUNDS);
+        let second = clip_polygon(&[b, a, [0.0, 14.0]], BOUNDS);
+        let cut = first.iter().find(|vertex| vertex[0] == BOUNDS[0][0] && vertex[1] > -3.3).unwrap();
+        assert!(second.contains(cut));
+    }
+}
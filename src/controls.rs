@@ -0,0 +1,272 @@
+use std::time::Duration;
+use winit::event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+use crate::math::{Vec3, Point3};
+use crate::projection::Camera;
+
+// Fly moves the camera freely, orbit keeps it on a sphere around a target in front of it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+    Fly,
+    Orbit,
+}
+
+#[derive(Clone, Copy)]
+enum Movement { Forward, Backward, Left, Right, Up, Down }
+
+impl Movement {
+    fn from_key(key: VirtualKeyCode) -> Option<Self> {
+        match key {
+            VirtualKeyCode::W => Some(Movement::Forward),
+            VirtualKeyCode::S => Some(Movement::Backward),
+            VirtualKeyCode::A => Some(Movement::Left),
+            VirtualKeyCode::D => Some(Movement::Right),
+            VirtualKeyCode::E => Some(Movement::Up),
+            VirtualKeyCode::Q => Some(Movement::Down),
+            _ => None,
+        }
+    }
+}
+
+pub struct CameraController {
+    mode: CameraMode,
+    position: Point3,
+    // Degrees, yaw 0 and pitch 0 look down +Z
+    yaw: f32,
+    pitch: f32,
+    orbit_distance: f32,
+    target: Point3,
+    // Units per second and degrees per pixel of mouse travel
+    speed: f32,
+    sensitivity: f32,
+    pressed: [bool; 6],
+    dragging: bool,
+    cursor: Option<[f64; 2]>,
+    look_delta: [f32; 2],
+    zoom_delta: f32,
+}
+
+impl CameraController {
+    const MAX_PITCH: f32 = 89.0;
+    // Slow frames, like a full raytracer pass, should not turn into one large jump
+    const MAX_FRAME_TIME: f32 = 0.1;
+    const UP: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+
+    pub fn new(camera: &Camera, orbit_distance: f32) -> Self {
+        let forward = camera.get_basis().forward;
+        let position = camera.get_position();
+
+        Self {
+            mode: CameraMode::Fly,
+            position,
+            yaw: forward.x.atan2(forward.z).to_degrees(),
+            pitch: forward.y.clamp(-1.0, 1.0).asin().to_degrees(),
+            orbit_distance,
+            target: position + forward * orbit_distance,
+            speed: 4.0,
+            sensitivity: 0.2,
+            pressed: [false; 6],
+            dragging: false,
+            cursor: None,
+            look_delta: [0.0, 0.0],
+            zoom_delta: 0.0,
+        }
+    }
+
+    // Orbit starts around the point the camera is looking at so the view does not jump
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            CameraMode::Fly => {
+                self.target = self.position + self.get_forward() * self.orbit_distance;
+                CameraMode::Orbit
+            },
+            CameraMode::Orbit => CameraMode::Fly,
+        };
+    }
+
+    fn get_forward(&self) -> Vec3 {
+        let (yaw, pitch) = (self.yaw.to_radians(), self.pitch.to_radians());
+        return Vec3::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos());
+    }
+
+    // Returns true when the event was used to drive the camera
+    pub fn process_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { state, virtual_keycode: Some(key), .. },
+                ..
+            } => {
+                let pressed = *state == ElementState::Pressed;
+                if *key == VirtualKeyCode::C {
+                    if pressed { self.toggle_mode(); }
+                    return true;
+                }
+                match Movement::from_key(*key) {
+                    Some(movement) => {
+                        self.pressed[movement as usize] = pressed;
+                        true
+                    },
+                    None => false,
+                }
+            },
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                self.dragging = *state == ElementState::Pressed;
+                true
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                if let (true, Some([x, y])) = (self.dragging, self.cursor) {
+                    self.look_delta[0] += (position.x - x) as f32;
+                    self.look_delta[1] += (position.y - y) as f32;
+                }
+                self.cursor = Some([position.x, position.y]);
+                self.dragging
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.zoom_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 100.0,
+                };
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // Applies the input gathered since the last frame, returns true when the camera moved
+    pub fn update(&mut self, camera: &mut Camera, frame_time: Duration) -> bool {
+        let seconds = frame_time.as_secs_f32().min(Self::MAX_FRAME_TIME);
+        let look_delta = std::mem::take(&mut self.look_delta);
+        let zoom_delta = std::mem::take(&mut self.zoom_delta);
+
+        let axis = |positive: Movement, negative: Movement| {
+            self.pressed[positive as usize] as i32 as f32 - self.pressed[negative as usize] as i32 as f32
+        };
+        let forward_input = axis(Movement::Forward, Movement::Backward);
+        let right_input = axis(Movement::Right, Movement::Left);
+        let up_input = axis(Movement::Up, Movement::Down);
+
+        let moved = look_delta != [0.0, 0.0] || zoom_delta != 0.0
+            || forward_input != 0.0 || right_input != 0.0 || up_input != 0.0;
+        if !moved { return false; }
+
+        // Cursor y grows downwards, dragging up looks up
+        self.yaw += look_delta[0] * self.sensitivity;
+        self.pitch = (self.pitch - look_delta[1] * self.sensitivity).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        let forward = self.get_forward();
+        let right = Self::UP.cross(forward).normalize();
+        let step = self.speed * seconds;
+
+        match self.mode {
+            CameraMode::Fly => {
+                let direction = forward * forward_input + right * right_input + Self::UP * up_input;
+                self.position = self.position + direction * step + forward * zoom_delta;
+            },
+            CameraMode::Orbit => {
+                // W/S and the wheel change the distance, A/D circle around the target and Q/E raise or lower it
+                let zoom = forward_input * step + zoom_delta;
+                self.orbit_distance = (self.orbit_distance - zoom).max(0.1);
+                self.target = self.target + Self::UP * up_input * step;
+                self.yaw -= right_input * step * 360.0 / (std::f32::consts::TAU * self.orbit_distance);
+
+                self.position = self.target - self.get_forward() * self.orbit_distance;
+            },
+        }
+
+        *camera = Camera::look_at(self.position, self.position + self.get_forward(), Self::UP);
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!((actual - expected).length() < 1e-4, "{:?} is not {:?}", actual, expected);
+    }
+
+    fn controller_at(eye: Point3, target: Point3) -> (CameraController, Camera) {
+        let camera = Camera::look_at(eye, target, CameraController::UP);
+        return (CameraController::new(&camera, 5.0), camera);
+    }
+
+    fn hold(controller: &mut CameraController, movement: Movement, held: bool) {
+        controller.pressed[movement as usize] = held;
+    }
+
+    // A held key moves a flying camera by speed times frame time along its own basis
+    #[test]
+    fn fly_keys_move_along_the_camera_basis() {
+        let eye = Point3::new(1.0, 2.0, 3.0);
+        let (mut controller, mut camera) = controller_at(eye, Point3::new(4.0, 2.0, 6.0));
+        let basis = camera.get_basis();
+
+        hold(&mut controller, Movement::Forward, true);
+        assert!(controller.update(&mut camera, Duration::from_millis(50)));
+        assert_near(camera.get_position() - eye, basis.forward * 0.2);
+
+        hold(&mut controller, Movement::Forward, false);
+        hold(&mut controller, Movement::Right, true);
+        let before = camera.get_position();
+        controller.update(&mut camera, Duration::from_millis(25));
+        assert_near(camera.get_position() - before, basis.right * 0.1);
+        // Moving does not turn the camera
+        assert_near(camera.get_basis().forward, basis.forward);
+
+        // A long frame is capped instead of jumping across the scene
+        let before = camera.get_position();
+        controller.update(&mut camera, Duration::from_secs(2));
+        assert_near(camera.get_position() - before, basis.right * 0.4);
+
+        hold(&mut controller, Movement::Right, false);
+        assert!(!controller.update(&mut camera, Duration::from_millis(50)));
+    }
+
+    // Circling keeps the camera on its sphere around the target, zooming changes the radius down to its minimum
+    #[test]
+    fn orbit_keeps_its_distance_until_zoomed() {
+        let (mut controller, mut camera) = controller_at(Point3::ORIGIN, Point3::new(0.0, 0.0, 1.0));
+        controller.toggle_mode();
+        let target = Point3::new(0.0, 0.0, 5.0);
+        let distance = |camera: &Camera| (target - camera.get_position()).length();
+
+        hold(&mut controller, Movement::Right, true);
+        for _ in 0..10 {
+            controller.update(&mut camera, Duration::from_millis(50));
+            assert!((distance(&camera) - 5.0).abs() < 1e-4);
+            assert_near(camera.get_basis().forward, (target - camera.get_position()).normalize());
+        }
+        assert!(camera.get_position().x.abs() > 0.1);
+        hold(&mut controller, Movement::Right, false);
+
+        controller.zoom_delta = 2.0;
+        controller.update(&mut camera, Duration::from_millis(50));
+        assert!((distance(&camera) - 3.0).abs() < 1e-4);
+
+        controller.zoom_delta = 100.0;
+        controller.update(&mut camera, Duration::from_millis(50));
+        assert!((distance(&camera) - 0.1).abs() < 1e-4);
+        assert!(camera.get_basis().forward.length() > 0.0);
+    }
+
+    // However far the mouse is dragged the view stops short of straight up or down, where it would be
+    // parallel to the up vector handed to look_at
+    #[test]
+    fn pitch_stops_short_of_the_poles() {
+        let max_sine = CameraController::MAX_PITCH.to_radians().sin();
+        for drag in [-1.0e6, 1.0e6] {
+            for mode_toggles in [0, 1] {
+                let (mut controller, mut camera) = controller_at(Point3::ORIGIN, Point3::new(0.0, 0.0, 1.0));
+                for _ in 0..mode_toggles { controller.toggle_mode(); }
+                controller.look_delta = [0.0, drag];
+                controller.update(&mut camera, Duration::from_millis(50));
+
+                assert!((controller.pitch.abs() - CameraController::MAX_PITCH).abs() < 1e-4);
+                let forward = camera.get_basis().forward;
+                assert!((forward.dot(CameraController::UP).abs() - max_sine).abs() < 1e-4, "{:?}", forward);
+                assert!(forward.cross(CameraController::UP).length() > 0.01);
+                // Dragging up looks up
+                assert_eq!(forward.y > 0.0, drag < 0.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,530 @@
+// 2D shapes for annotations and debug overlays, drawn straight onto a canvas in pixel coordinates.
+// Like the renderers, the origin is the middle of the canvas and y grows upwards
+use crate::clipping::{canvas_bounds, clip_line};
+use crate::font;
+use crate::math::Color;
+use crate::utilities::interpolate;
+
+// Control points closer than this to the chord, in pixels, end the subdivision of a curve
+const FLATNESS: f32 = 0.25;
+// Keeps subdivision finite for curves with huge or non-finite control points
+const MAX_SUBDIVISIONS: u32 = 16;
+
+pub trait Canvas {
+    fn get_canvas_size(&self) -> [i32; 2];
+    fn put_pixel(&mut self, x: i32, y: i32, rgb: Color);
+}
+
+// Which parts of a self-intersecting polygon count as inside
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillRule {
+    // Inside where a ray from the point crosses the outline an odd number of times
+    EvenOdd,
+    // Inside where the outline winds around the point at all
+    NonZero,
+}
+
+fn is_on_canvas(canvas: &impl Canvas, x: i32, y: i32) -> bool {
+    let [width, height] = canvas.get_canvas_size();
+    return x >= -width / 2 && x < width / 2 && y >= -height / 2 && y < height / 2;
+}
+
+fn put_visible_pixel(canvas: &mut impl Canvas, x: i32, y: i32, rgb: Color) {
+    if is_on_canvas(canvas, x, y) {
+        canvas.put_pixel(x, y, rgb);
+    }
+}
+
+// Pixels from x_start to x_end on row y, limited to the canvas
+fn draw_span(canvas: &mut impl Canvas, y: i32, x_start: i32, x_end: i32, rgb: Color) {
+    let [width, height] = canvas.get_canvas_size();
+    if y < -height / 2 || y >= height / 2 { return; }
+    for x in x_start.max(-width / 2) ..= x_end.min(width / 2 - 1) {
+        canvas.put_pixel(x, y, rgb);
+    }
+}
+
+// Only the part of the line over the canvas is walked, the pixels are the ones the whole line would get
+pub fn draw_line(canvas: &mut impl Canvas, point_a: [i32; 2], point_b: [i32; 2], rgb: Color) {
+    let [a, b] = [point_a, point_b].map(|point| point.map(|value| value as f32));
+    let Some((start, end)) = clip_line(a, b, canvas_bounds(canvas.get_canvas_size())) else { return; };
+
+    // Steps along x for flat lines and along y for steep ones, with the other coordinate interpolated
+    let (major, minor) = if (point_b[0] - point_a[0]).abs() > (point_b[1] - point_a[1]).abs() { (0, 1) } else { (1, 0) };
+    let slope = if b[major] == a[major] { 0.0 } else { (b[minor] - a[minor]) / (b[major] - a[major]) };
+    let minor_at = |step: i32| a[minor] + slope * (step as f32 - a[major]);
+
+    let first = start[major].min(end[major]).ceil() as i32;
+    let last = start[major].max(end[major]).floor() as i32;
+    if first > last { return; }
+    let minor_values = interpolate(first, minor_at(first), last, minor_at(last));
+    for (step, minor_value) in (first ..= last).zip(minor_values) {
+        let mut pixel = [0; 2];
+        pixel[major] = step;
+        pixel[minor] = minor_value;
+        // Rounding can still land half a pixel past the edge
+        put_visible_pixel(canvas, pixel[0], pixel[1], rgb);
+    }
+}
+
+// Connects the points in order, closing the outline back to the first one when asked
+pub fn draw_polyline(canvas: &mut impl Canvas, points: &[[f32; 2]], closed: bool, rgb: Color) {
+    let rounded: Vec<[i32; 2]> = points.iter().map(|point| point.map(|value| value.round() as i32)).collect();
+    for pair in rounded.windows(2) {
+        draw_line(canvas, pair[0], pair[1], rgb);
+    }
+    if closed && rounded.len() > 2 {
+        draw_line(canvas, rounded[rounded.len() - 1], rounded[0], rgb);
+    }
+}
+
+// Corner is the bottom left pixel, size counts pixels so the rectangle covers corner to corner + size - 1
+pub fn draw_rect(canvas: &mut impl Canvas, corner: [i32; 2], size: [i32; 2], rgb: Color) {
+    if size[0] <= 0 || size[1] <= 0 { return; }
+    let [left, bottom] = corner;
+    let [right, top] = [corner[0] + size[0] - 1, corner[1] + size[1] - 1];
+
+    draw_span(canvas, bottom, left, right, rgb);
+    if top != bottom { draw_span(canvas, top, left, right, rgb); }
+    for y in bottom + 1 .. top {
+        put_visible_pixel(canvas, left, y, rgb);
+        if right != left { put_visible_pixel(canvas, right, y, rgb); }
+    }
+}
+
+pub fn fill_rect(canvas: &mut impl Canvas, corner: [i32; 2], size: [i32; 2], rgb: Color) {
+    let half_height = canvas.get_canvas_size()[1] / 2;
+    for y in corner[1].max(-half_height) .. (corner[1] + size[1]).min(half_height) {
+        draw_span(canvas, y, corner[0], corner[0] + size[0] - 1, rgb);
+    }
+}
+
+// Midpoint circle: walks one eighth of the circle from (radius, 0) towards the diagonal with integer
+// steps, choosing between the two candidate pixels by the sign of the circle function between them
+fn circle_octant(radius: i32) -> Vec<[i32; 2]> {
+    let mut points = vec![];
+    let (mut x, mut y) = (radius, 0);
+    let mut decision = 1 - radius;
+
+    while x >= y {
+        points.push([x, y]);
+        y += 1;
+        if decision < 0 {
+            decision += 2 * y + 1;
+        } else {
+            x -= 1;
+            decision += 2 * (y - x) + 1;
+        }
+    }
+
+    return points;
+}
+
+pub fn draw_circle(canvas: &mut impl Canvas, center: [i32; 2], radius: i32, rgb: Color) {
+    if radius < 0 { return; }
+
+    for [x, y] in circle_octant(radius) {
+        let mirrored = [[x, y], [y, x], [-y, x], [-x, y], [-x, -y], [-y, -x], [y, -x], [x, -y]];
+        // Points on the axes and the diagonals mirror onto themselves, draw them once
+        for (idx, &[dx, dy]) in mirrored.iter().enumerate() {
+            if mirrored[..idx].contains(&[dx, dy]) { continue; }
+            put_visible_pixel(canvas, center[0] + dx, center[1] + dy, rgb);
+        }
+    }
+}
+
+// Rows reach out to the outline drawn by draw_circle, so a filled circle and its outline line up
+pub fn fill_circle(canvas: &mut impl Canvas, center: [i32; 2], radius: i32, rgb: Color) {
+    if radius < 0 { return; }
+
+    let mut half_widths = vec![0; radius as usize + 1];
+    for [x, y] in circle_octant(radius) {
+        half_widths[y as usize] = half_widths[y as usize].max(x);
+        half_widths[x as usize] = half_widths[x as usize].max(y);
+    }
+    fill_symmetric_rows(canvas, center, &half_widths, rgb);
+}
+
+// Midpoint ellipse: the first quadrant from (0, ry) to (rx, 0). Where the outline is flatter than 45
+// degrees x steps every time, past that y does. Decision values are scaled by 4 to stay in integers
+fn ellipse_quadrant(radii: [i32; 2]) -> Vec<[i32; 2]> {
+    let [rx, ry] = radii.map(|radius| radius as i64);
+    if ry == 0 {
+        return (0 ..= rx).map(|x| [x as i32, 0]).collect();
+    }
+
+    let (rx2, ry2) = (rx * rx, ry * ry);
+    let mut points = vec![];
+    let (mut x, mut y) = (0, ry);
+
+    let mut decision = 4 * ry2 - 4 * rx2 * ry + rx2;
+    while ry2 * x < rx2 * y {
+        points.push([x as i32, y as i32]);
+        x += 1;
+        if decision < 0 {
+            decision += 4 * ry2 * (2 * x + 1);
+        } else {
+            y -= 1;
+            decision += 4 * ry2 * (2 * x + 1) - 8 * rx2 * y;
+        }
+    }
+
+    let mut decision = ry2 * (2 * x + 1).pow(2) + 4 * rx2 * (y - 1).pow(2) - 4 * rx2 * ry2;
+    while y >= 0 {
+        points.push([x as i32, y as i32]);
+        y -= 1;
+        if decision > 0 {
+            decision += 4 * rx2 * (1 - 2 * y);
+        } else {
+            x += 1;
+            decision += 8 * ry2 * x - 8 * rx2 * y + 4 * rx2;
+        }
+    }
+    // Very flat ellipses can leave the loop on the axis short of its end, the rest of the axis is outline
+    let last_x = points.last().map_or(0, |point| point[0]);
+    points.extend((last_x + 1 ..= rx as i32).map(|x| [x, 0]));
+
+    return points;
+}
+
+pub fn draw_ellipse(canvas: &mut impl Canvas, center: [i32; 2], radii: [i32; 2], rgb: Color) {
+    if radii[0] < 0 || radii[1] < 0 { return; }
+
+    for [x, y] in ellipse_quadrant(radii) {
+        let mirrored = [[x, y], [-x, y], [-x, -y], [x, -y]];
+        for (idx, &[dx, dy]) in mirrored.iter().enumerate() {
+            if mirrored[..idx].contains(&[dx, dy]) { continue; }
+            put_visible_pixel(canvas, center[0] + dx, center[1] + dy, rgb);
+        }
+    }
+}
+
+pub fn fill_ellipse(canvas: &mut impl Canvas, center: [i32; 2], radii: [i32; 2], rgb: Color) {
+    if radii[0] < 0 || radii[1] < 0 { return; }
+
+    let mut half_widths = vec![0; radii[1] as usize + 1];
+    for [x, y] in ellipse_quadrant(radii) {
+        half_widths[y as usize] = half_widths[y as usize].max(x);
+    }
+    fill_symmetric_rows(canvas, center, &half_widths, rgb);
+}
+
+// Rows above and below the center with the half width of each distance from it
+fn fill_symmetric_rows(canvas: &mut impl Canvas, center: [i32; 2], half_widths: &[i32], rgb: Color) {
+    for (dy, &half_width) in half_widths.iter().enumerate() {
+        let dy = dy as i32;
+        draw_span(canvas, center[1] + dy, center[0] - half_width, center[0] + half_width, rgb);
+        if dy != 0 {
+            draw_span(canvas, center[1] - dy, center[0] - half_width, center[0] + half_width, rgb);
+        }
+    }
+}
+
+// Scanline fill of any simple or self-intersecting polygon. Every row of pixel centers is crossed with the
+// outline, and the pixels between two crossings are filled when the rule says that stretch is inside.
+// Pixel centers exactly on the outline follow the same top-left rule as the rasterizer's triangles
+pub fn fill_polygon(canvas: &mut impl Canvas, points: &[[f32; 2]], rule: FillRule, rgb: Color) {
+    if points.len() < 3 { return; }
+
+    let [width, height] = canvas.get_canvas_size();
+    let lowest = points.iter().map(|point| point[1]).fold(f32::INFINITY, f32::min);
+    let highest = points.iter().map(|point| point[1]).fold(f32::NEG_INFINITY, f32::max);
+    if !lowest.is_finite() || !highest.is_finite() { return; }
+    let first_row = (lowest.floor() as i32 + 1).max(-height / 2);
+    let last_row = (highest.floor() as i32).min(height / 2 - 1);
+
+    // x of each crossing with +1 for an upward edge and -1 for a downward one
+    let mut crossings: Vec<(f32, i32)> = vec![];
+    for y in first_row ..= last_row {
+        let row = y as f32;
+        crossings.clear();
+        for (idx, &p) in points.iter().enumerate() {
+            let q = points[(idx + 1) % points.len()];
+            let (low, high, direction) = if p[1] < q[1] { (p, q, 1) } else { (q, p, -1) };
+            // Half open in y, so a vertex between two edges is crossed once and flat edges never are
+            if low[1] < row && row <= high[1] {
+                let x = low[0] + (row - low[1]) * (high[0] - low[0]) / (high[1] - low[1]);
+                crossings.push((x, direction));
+            }
+        }
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut winding = 0;
+        for pair in crossings.windows(2) {
+            winding += pair[0].1;
+            let inside = match rule {
+                FillRule::EvenOdd => winding % 2 != 0,
+                FillRule::NonZero => winding != 0,
+            };
+            if !inside { continue; }
+
+            let x_start = (pair[0].0.ceil() as i32).max(-width / 2);
+            let x_end = (pair[1].0.ceil() as i32 - 1).min(width / 2 - 1);
+            for x in x_start ..= x_end {
+                canvas.put_pixel(x, y, rgb);
+            }
+        }
+    }
+}
+
+pub fn draw_quadratic_bezier(canvas: &mut impl Canvas, points: [[f32; 2]; 3], rgb: Color) {
+    // Any quadratic is also a cubic with its inner control points two thirds of the way to the middle one
+    let [start, control, end] = points;
+    let toward_control = |point: [f32; 2]| [0, 1].map(|axis| point[axis] + (control[axis] - point[axis]) * 2.0 / 3.0);
+    draw_cubic_bezier(canvas, [start, toward_control(start), toward_control(end), end], rgb);
+}
+
+pub fn draw_cubic_bezier(canvas: &mut impl Canvas, points: [[f32; 2]; 4], rgb: Color) {
+    let mut polyline = vec![points[0]];
+    flatten_cubic(points, 0, &mut polyline);
+    draw_polyline(canvas, &polyline, false, rgb);
+}
+
+// Adaptive subdivision: a curve whose inner control points are close to its chord is drawn as that chord,
+// others are split in half with de Casteljau's construction. Tight bends get many segments, flat stretches few
+fn flatten_cubic(points: [[f32; 2]; 4], depth: u32, polyline: &mut Vec<[f32; 2]>) {
+    if depth >= MAX_SUBDIVISIONS || is_flat(points) {
+        polyline.push(points[3]);
+        return;
+    }
+
+    let midpoint = |p: [f32; 2], q: [f32; 2]| [(p[0] + q[0]) / 2.0, (p[1] + q[1]) / 2.0];
+    let [p0, p1, p2, p3] = points;
+    let (p01, p12, p23) = (midpoint(p0, p1), midpoint(p1, p2), midpoint(p2, p3));
+    let (p012, p123) = (midpoint(p01, p12), midpoint(p12, p23));
+    let middle = midpoint(p012, p123);
+
+    flatten_cubic([p0, p01, p012, middle], depth + 1, polyline);
+    flatten_cubic([middle, p123, p23, p3], depth + 1, polyline);
+}
+
+fn is_flat(points: [[f32; 2]; 4]) -> bool {
+    let [start, first, second, end] = points;
+    // The curve stays inside the hull of its control points, so it is as close to the chord as they are
+    return distance_to_segment(first, start, end) <= FLATNESS && distance_to_segment(second, start, end) <= FLATNESS;
+}
+
+fn distance_to_segment(point: [f32; 2], start: [f32; 2], end: [f32; 2]) -> f32 {
+    let along = [end[0] - start[0], end[1] - start[1]];
+    let offset = [point[0] - start[0], point[1] - start[1]];
+    let length_squared = along[0] * along[0] + along[1] * along[1];
+    let t = if length_squared > 0.0 { ((offset[0] * along[0] + offset[1] * along[1]) / length_squared).clamp(0.0, 1.0) } else { 0.0 };
+    return ((offset[0] - along[0] * t).powi(2) + (offset[1] - along[1] * t).powi(2)).sqrt();
+}
+
+// Text in the built-in font with the top left corner of the first character at position. Every font pixel
+// becomes a scale by scale block and a newline starts the next line below
+pub fn draw_text(canvas: &mut impl Canvas, position: [i32; 2], text: &str, scale: i32, rgb: Color) {
+    let scale = scale.max(1);
+    let [advance_x, advance_y] = font::ADVANCE.map(|advance| advance * scale);
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let top = position[1] - line_idx as i32 * advance_y;
+        for (column, character) in line.chars().enumerate() {
+            let left = position[0] + column as i32 * advance_x;
+            for (row, bits) in font::get_glyph(character).iter().enumerate() {
+                let block_top = top - row as i32 * scale;
+                for bit in 0 .. font::GLYPH_SIZE[0] {
+                    if bits >> (font::GLYPH_SIZE[0] - 1 - bit) & 1 == 0 { continue; }
+                    fill_rect(canvas, [left + bit * scale, block_top - scale + 1], [scale, scale], rgb);
+                }
+            }
+        }
+    }
+}
+
+// Width and height in pixels of the box draw_text fills for this text, without the spacing after the last
+// character and below the last line
+pub fn text_size(text: &str, scale: i32) -> [i32; 2] {
+    let scale = scale.max(1);
+    let columns = text.lines().map(|line| line.chars().count()).max().unwrap_or(0) as i32;
+    let lines = text.lines().count() as i32;
+    if columns == 0 { return [0, 0]; }
+
+    let [advance_x, advance_y] = font::ADVANCE;
+    let [glyph_width, glyph_height] = font::GLYPH_SIZE;
+    return [((columns - 1) * advance_x + glyph_width) * scale, ((lines - 1) * advance_y + glyph_height) * scale];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use proptest::prelude::*;
+
+    // Counts how often each pixel was drawn
+    struct TestCanvas {
+        size: [i32; 2],
+        pixels: HashMap<[i32; 2], usize>,
+    }
+
+    impl Canvas for TestCanvas {
+        fn get_canvas_size(&self) -> [i32; 2] {
+            return self.size;
+        }
+
+        fn put_pixel(&mut self, x: i32, y: i32, _rgb: Color) {
+            *self.pixels.entry([x, y]).or_insert(0) += 1;
+        }
+    }
+
+    fn draw(size: i32, draw: impl FnOnce(&mut TestCanvas)) -> HashMap<[i32; 2], usize> {
+        let mut canvas = TestCanvas { size: [size, size], pixels: HashMap::new() };
+        draw(&mut canvas);
+        return canvas.pixels;
+    }
+
+    // Consecutive outline points touch, sideways or on a diagonal
+    fn is_connected(points: &[[i32; 2]]) -> bool {
+        return points.windows(2).all(|pair| (pair[0][0] - pair[1][0]).abs() <= 1 && (pair[0][1] - pair[1][1]).abs() <= 1);
+    }
+
+    fn control_point() -> impl Strategy<Value = [f32; 2]> {
+        [-100.0f32..100.0, -100.0f32..100.0]
+    }
+
+    const STAR: [[f32; 2]; 5] = [[0.0, 20.0], [11.8, -16.2], [-19.0, 6.2], [19.0, 6.2], [-11.8, -16.2]];
+
+    proptest! {
+        #[test]
+        fn circle_outlines_stay_on_the_radius(radius in 0i32..120) {
+            let octant = circle_octant(radius);
+            prop_assert!(is_connected(&octant));
+            for [x, y] in octant {
+                prop_assert!(((x * x + y * y) as f32).sqrt() - radius as f32 <= 0.5 + 1e-4);
+                prop_assert!(radius as f32 - ((x * x + y * y) as f32).sqrt() <= 0.5 + 1e-4);
+            }
+
+            let pixels = draw(300, |canvas| draw_circle(canvas, [0, 0], radius, Color::WHITE));
+            prop_assert!(pixels.values().all(|&count| count == 1));
+        }
+
+        #[test]
+        fn ellipse_outlines_stay_on_the_ellipse(rx in 0i32..120, ry in 0i32..120) {
+            let quadrant = ellipse_quadrant([rx, ry]);
+            prop_assert!(is_connected(&quadrant));
+            prop_assert_eq!(quadrant.first().copied(), Some(if ry == 0 { [0, 0] } else { [0, ry] }));
+            prop_assert_eq!(quadrant.last().copied(), Some([rx, 0]));
+            for [x, y] in quadrant {
+                // The nearest point on the outline is within a pixel, checked along both axes
+                let (x, y, rx, ry) = (x as f32, y as f32, rx as f32, ry as f32);
+                let x_on_outline = if ry == 0.0 { x } else { rx * (1.0 - (y / ry).powi(2)).max(0.0).sqrt() };
+                let y_on_outline = if rx == 0.0 { y } else { ry * (1.0 - (x / rx).powi(2)).max(0.0).sqrt() };
+                prop_assert!((x - x_on_outline).abs() <= 1.0 || (y - y_on_outline).abs() <= 1.0);
+            }
+        }
+
+        // A filled circle covers its outline and every pixel whose center lies inside the radius
+        #[test]
+        fn filled_circles_match_their_outline(radius in 0i32..80, center in [-40i32..40, -40i32..40]) {
+            let outline = draw(300, |canvas| draw_circle(canvas, center, radius, Color::WHITE));
+            let filled = draw(300, |canvas| fill_circle(canvas, center, radius, Color::WHITE));
+
+            prop_assert!(filled.values().all(|&count| count == 1));
+            prop_assert!(outline.keys().all(|pixel| filled.contains_key(pixel)));
+            for dy in -radius ..= radius {
+                for dx in -radius ..= radius {
+                    if dx * dx + dy * dy <= radius * radius {
+                        prop_assert!(filled.contains_key(&[center[0] + dx, center[1] + dy]));
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn filled_polygons_match_the_rect_they_outline(corner in [-50i32..50, -50i32..50], size in [1i32..40, 1i32..40]) {
+            // Outline through the outer pixel edges, so exactly the pixels of the rectangle are inside
+            let [left, bottom] = corner.map(|value| value as f32 - 0.5);
+            let [right, top] = [left + size[0] as f32, bottom + size[1] as f32];
+            let outline = [[left, bottom], [right, bottom], [right, top], [left, top]];
+
+            let rect = draw(200, |canvas| fill_rect(canvas, corner, size, Color::WHITE));
+            for rule in [FillRule::EvenOdd, FillRule::NonZero] {
+                let polygon = draw(200, |canvas| fill_polygon(canvas, &outline, rule, Color::WHITE));
+                prop_assert_eq!(&polygon, &rect);
+            }
+            prop_assert_eq!(rect.len() as i32, size[0] * size[1]);
+        }
+
+        // The points of an adaptively flattened curve stay within the flatness of the exact curve
+        #[test]
+        fn flattened_curves_stay_close_to_the_curve(points in [control_point(), control_point(), control_point(), control_point()]) {
+            let mut polyline = vec![points[0]];
+            flatten_cubic(points, 0, &mut polyline);
+            prop_assert_eq!(polyline.first().copied(), Some(points[0]));
+            prop_assert_eq!(polyline.last().copied(), Some(points[3]));
+
+            let curve_at = |t: f32| {
+                let weights = [(1.0 - t).powi(3), 3.0 * (1.0 - t).powi(2) * t, 3.0 * (1.0 - t) * t * t, t.powi(3)];
+                [0, 1].map(|axis| (0 .. 4).map(|idx| weights[idx] * points[idx][axis]).sum::<f32>())
+            };
+            let distance_to_polyline = |point: [f32; 2]| {
+                polyline.windows(2).map(|segment| distance_to_segment(point, segment[0], segment[1])).fold(f32::INFINITY, f32::min)
+            };
+            for step in 0 ..= 64 {
+                prop_assert!(distance_to_polyline(curve_at(step as f32 / 64.0)) <= FLATNESS + 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn star_center_depends_on_the_fill_rule() {
+        // The pentagram's center is wound around twice: inside for non-zero, outside for even-odd
+        let even_odd = draw(100, |canvas| fill_polygon(canvas, &STAR, FillRule::EvenOdd, Color::WHITE));
+        let non_zero = draw(100, |canvas| fill_polygon(canvas, &STAR, FillRule::NonZero, Color::WHITE));
+
+        assert!(!even_odd.contains_key(&[0, 0]));
+        assert!(non_zero.contains_key(&[0, 0]));
+        // The points of the star are inside either way
+        assert!(even_odd.contains_key(&[0, 15]) && non_zero.contains_key(&[0, 15]));
+        assert!(even_odd.keys().all(|pixel| non_zero.contains_key(pixel)));
+    }
+
+    #[test]
+    fn shapes_past_the_edge_only_draw_what_is_on_the_canvas() {
+        let pixels = draw(40, |canvas| {
+            draw_circle(canvas, [0, 0], 500, Color::WHITE);
+            fill_rect(canvas, [-1000, -1000], [2000, 5], Color::WHITE);
+            draw_rect(canvas, [15, 15], [30, 30], Color::WHITE);
+            fill_polygon(canvas, &[[-1e6, -1e6], [1e6, -1e6], [0.0, -15.5]], FillRule::NonZero, Color::WHITE);
+        });
+        assert!(pixels.keys().all(|&[x, y]| (-20 .. 20).contains(&x) && (-20 .. 20).contains(&y)));
+        // The rect reaches into the canvas from the top right corner
+        assert!(pixels.contains_key(&[15, 19]) && pixels.contains_key(&[19, 15]));
+    }
+
+    #[test]
+    fn text_fills_the_box_it_reports() {
+        for (text, scale) in [("Hg", 1), ("FPS 59.9\nRays 2560000", 2), ("|\n_", 3)] {
+            let pixels = draw(400, |canvas| draw_text(canvas, [-150, 100], text, scale, Color::WHITE));
+            let [width, height] = text_size(text, scale);
+            let columns: Vec<i32> = pixels.keys().map(|pixel| pixel[0]).collect();
+            let rows: Vec<i32> = pixels.keys().map(|pixel| pixel[1]).collect();
+
+            // The box is tight: the glyphs touch its left, right, top and bottom edges
+            assert_eq!(*columns.iter().min().unwrap(), -150, "{:?}", text);
+            assert_eq!(*columns.iter().max().unwrap(), -150 + width - 1, "{:?}", text);
+            assert_eq!(*rows.iter().max().unwrap(), 100, "{:?}", text);
+            assert_eq!(*rows.iter().min().unwrap(), 100 - height + 1, "{:?}", text);
+        }
+        assert_eq!(text_size("", 2), [0, 0]);
+    }
+
+    #[test]
+    fn glyph_pixels_become_scaled_blocks() {
+        let single = draw(100, |canvas| draw_text(canvas, [0, 0], "A", 1, Color::WHITE));
+        let scaled = draw(100, |canvas| draw_text(canvas, [0, 0], "A", 3, Color::WHITE));
+        assert_eq!(scaled.len(), single.len() * 9);
+        for &[x, y] in single.keys() {
+            assert!((0 .. 3).all(|dx| (0 .. 3).all(|dy| scaled.contains_key(&[x * 3 + dx, y * 3 - dy]))));
+        }
+    }
+
+    #[test]
+    fn quadratic_curves_pass_through_their_ends() {
+        let pixels = draw(100, |canvas| draw_quadratic_bezier(canvas, [[-30.0, -20.0], [0.0, 40.0], [30.0, -20.0]], Color::WHITE));
+        assert!(pixels.contains_key(&[-30, -20]) && pixels.contains_key(&[30, -20]));
+        // The middle of the curve is halfway between the ends and the control point
+        assert!(pixels.contains_key(&[0, 10]));
+    }
+}
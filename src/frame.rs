@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::geometry::Vertex;
+use crate::math::Color;
+
+// Clear color of the window, shows wherever the renderer did not draw a point
+pub const BACKGROUND: Color = Color::new(25.5, 51.0, 76.5);
+
+// 8-bit RGB image of a renderer pass, rows go from the top of the window to the bottom
+pub struct Frame {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(width: usize, height: usize, background: Color) -> Self {
+        let [r, g, b] = to_bytes(background.to_normalized());
+        let pixels = [r, g, b].repeat(width * height);
+        Self { width, height, pixels }
+    }
+
+    // Rebuilds the image from the points sent to wgpu, later points overwrite earlier ones like in the draw call
+    pub fn from_vertices(vertices: &[Vertex], canvas: [i32; 2], background: Color) -> Self {
+        let mut frame = Self::new(canvas[0] as usize, canvas[1] as usize, background);
+
+        for vertex in vertices {
+            // Same mapping as clip space to the window, canvas y grows upwards while rows grow downwards
+            let x = (vertex.position[0] * (canvas[0] / 2) as f32).round() as i32;
+            let y = (vertex.position[1] * (canvas[1] / 2) as f32).round() as i32;
+            let column = x + canvas[0] / 2;
+            let row = canvas[1] / 2 - y;
+            if column < 0 || column >= canvas[0] || row < 0 || row >= canvas[1] {
+                continue;
+            }
+            frame.set_pixel(column as usize, row as usize, to_bytes(vertex.color));
+        }
+
+        return frame;
+    }
+
+    pub fn get_size(&self) -> [usize; 2] {
+        return [self.width, self.height];
+    }
+
+    pub fn get_pixel(&self, column: usize, row: usize) -> [u8; 3] {
+        let idx = (row * self.width + column) * 3;
+        return [self.pixels[idx], self.pixels[idx + 1], self.pixels[idx + 2]];
+    }
+
+    pub fn set_pixel(&mut self, column: usize, row: usize, rgb: [u8; 3]) {
+        let idx = (row * self.width + column) * 3;
+        self.pixels[idx..idx + 3].copy_from_slice(&rgb);
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        return self.pixels.as_slice();
+    }
+
+    pub fn load_png(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+
+        let channels = match info.color_type {
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "expected an rgb or rgba png")),
+        };
+        let pixels = buffer[..info.buffer_size()]
+            .chunks_exact(channels)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+
+        Ok(Self { width: info.width as usize, height: info.height as usize, pixels })
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        return Ok(());
+    }
+}
+
+pub fn to_bytes(color: [f32; 3]) -> [u8; 3] {
+    return color.map(|channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8);
+}
+
+// screenshot-<unix seconds>-<milliseconds>.png inside the given directory
+pub fn screenshot_path(directory: impl AsRef<Path>) -> PathBuf {
+    return timestamped_path(directory, "screenshot");
+}
+
+// <prefix>-<unix seconds>-<milliseconds>.png inside the given directory
+pub fn timestamped_path(directory: impl AsRef<Path>, prefix: &str) -> PathBuf {
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let name = format!("{}-{}-{:03}.png", prefix, elapsed.as_secs(), elapsed.subsec_millis());
+    return directory.as_ref().join(name);
+}
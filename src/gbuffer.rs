@@ -0,0 +1,158 @@
+use std::io;
+use std::path::Path;
+use crate::frame::{self, Frame};
+use crate::math::{Vec3, Point3, Color};
+
+// What the deferred rasterizer keeps of the nearest surface on a pixel, everything the lighting pass needs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub position: Point3,
+    pub normal: Vec3,
+    // Texture and vertex colors before any light
+    pub albedo: Color,
+    // Specular exponent like the raytracer's, zero or below for a matte surface
+    pub specular: f32,
+}
+
+// Channels of the G-buffer that can be looked at as images
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    Position,
+    Normal,
+    Albedo,
+    Material,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 4] = [Channel::Position, Channel::Normal, Channel::Albedo, Channel::Material];
+
+    pub fn get_name(&self) -> &'static str {
+        return match self {
+            Channel::Position => "position",
+            Channel::Normal => "normal",
+            Channel::Albedo => "albedo",
+            Channel::Material => "material",
+        }
+    }
+}
+
+// Surface samples on the canvas grid, centered like the rasterizer's canvas with y growing upwards
+pub struct GBuffer {
+    size: [i32; 2],
+    samples: Vec<Option<Sample>>,
+}
+
+impl GBuffer {
+    // Specular exponents at or above this show as white in the material image
+    const MAX_SPECULAR: f32 = 1000.0;
+
+    pub fn new(size: [i32; 2]) -> Self {
+        Self { size, samples: vec![None; (size[0] * size[1]) as usize] }
+    }
+
+    pub fn get_size(&self) -> [i32; 2] {
+        return self.size;
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.fill(None);
+    }
+
+    fn sample_index(&self, x: i32, y: i32) -> Option<usize> {
+        let x_idx = x + self.size[0] / 2;
+        let y_idx = y + self.size[1] / 2;
+        if x_idx < 0 || x_idx >= self.size[0] || y_idx < 0 || y_idx >= self.size[1] {
+            return None;
+        }
+        return Some((y_idx * self.size[0] + x_idx) as usize);
+    }
+
+    pub fn get_sample(&self, x: i32, y: i32) -> Option<&Sample> {
+        return self.sample_index(x, y).and_then(|idx| self.samples[idx].as_ref());
+    }
+
+    // The rasterizer depth tests before writing, so a later sample always replaces the one before it
+    pub fn put_sample(&mut self, x: i32, y: i32, sample: Sample) {
+        if let Some(idx) = self.sample_index(x, y) {
+            self.samples[idx] = Some(sample);
+        }
+    }
+
+    // Every covered pixel with its sample, row by row from the bottom
+    pub fn iter(&self) -> impl Iterator<Item = (i32, i32, &Sample)> {
+        let [width, height] = self.size;
+        return self.samples.iter().enumerate().filter_map(move |(idx, sample)| {
+            let (x, y) = (idx as i32 % width - width / 2, idx as i32 / width - height / 2);
+            sample.as_ref().map(|sample| (x, y, sample))
+        });
+    }
+
+    // Debug image of one channel, black where nothing was drawn. Normals map from -1..1 to the color range,
+    // positions from the box around the covered samples, and the material shows the specular exponent in gray
+    pub fn to_frame(&self, channel: Channel) -> Frame {
+        let [width, height] = self.size;
+        let mut image = Frame::new(width as usize, height as usize, Color::BLACK);
+
+        let positions = self.iter().map(|(_, _, sample)| sample.position.to_array());
+        let (low, high) = positions.fold(([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]), |(low, high), position| {
+            ([0, 1, 2].map(|axis| low[axis].min(position[axis])), [0, 1, 2].map(|axis| high[axis].max(position[axis])))
+        });
+
+        for (x, y, sample) in self.iter() {
+            let color = match channel {
+                Channel::Position => {
+                    let position = sample.position.to_array();
+                    [0, 1, 2].map(|axis| if high[axis] > low[axis] { (position[axis] - low[axis]) / (high[axis] - low[axis]) } else { 0.5 })
+                },
+                Channel::Normal => sample.normal.to_array().map(|value| value * 0.5 + 0.5),
+                Channel::Albedo => sample.albedo.to_normalized(),
+                Channel::Material => [(sample.specular / Self::MAX_SPECULAR).clamp(0.0, 1.0); 3],
+            };
+
+            // Same rows as the frames of the rasterizer
+            let (column, row) = (x + width / 2, height / 2 - y);
+            if row < 0 || row >= height { continue; }
+            image.set_pixel(column as usize, row as usize, frame::to_bytes(color));
+        }
+        return image;
+    }
+
+    pub fn save_png(&self, channel: Channel, path: impl AsRef<Path>) -> io::Result<()> {
+        return self.to_frame(channel).save_png(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(position: Point3, normal: Vec3, specular: f32) -> Sample {
+        return Sample { position, normal, albedo: Color::new(255.0, 128.0, 0.0), specular };
+    }
+
+    #[test]
+    fn channels_export_as_images() {
+        let mut gbuffer = GBuffer::new([4, 4]);
+        gbuffer.put_sample(-1, 1, sample(Point3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0), 500.0));
+        gbuffer.put_sample(1, 0, sample(Point3::new(2.0, 4.0, 2.0), Vec3::new(1.0, 0.0, 0.0), -1.0));
+        gbuffer.put_sample(9, 9, sample(Point3::ORIGIN, Vec3::new(1.0, 0.0, 0.0), 0.0));
+        assert_eq!(gbuffer.iter().count(), 2);
+
+        // Canvas (-1, 1) lands in column 1 of row 1, canvas (1, 0) in column 3 of row 2
+        let normal = gbuffer.to_frame(Channel::Normal);
+        assert_eq!(normal.get_pixel(1, 1), [128, 128, 0]);
+        assert_eq!(normal.get_pixel(3, 2), [255, 128, 128]);
+        assert_eq!(normal.get_pixel(0, 0), [0, 0, 0]);
+
+        assert_eq!(gbuffer.to_frame(Channel::Albedo).get_pixel(3, 2), [255, 128, 0]);
+        let position = gbuffer.to_frame(Channel::Position);
+        assert_eq!(position.get_pixel(1, 1), [0, 0, 128]);
+        assert_eq!(position.get_pixel(3, 2), [255, 255, 128]);
+        let material = gbuffer.to_frame(Channel::Material);
+        assert_eq!(material.get_pixel(1, 1), [128, 128, 128]);
+        assert_eq!(material.get_pixel(3, 2), [0, 0, 0]);
+
+        gbuffer.clear();
+        assert!(gbuffer.get_sample(-1, 1).is_none());
+    }
+}
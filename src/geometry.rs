@@ -1,9 +1,7 @@
-use crate::utilities::{
-    dot_product,
-    vector_length,
-    vector_subtraction,
-    divide_vector,
-};
+use std::f32::consts::PI;
+use std::rc::Rc;
+use crate::math::{Vec3, Point3, Color};
+use crate::texture::Texture;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -33,34 +31,50 @@ impl Vertex {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum LightMode {
     Ambient,
     Point,
     Directional,
+    // A point light shining along `direction`, limited to a cone with this angle in degrees between its axis and its edge
+    Spot(f32),
 }
 
+#[derive(Clone)]
 pub struct Light {
     pub mode: LightMode,
     pub intensity: f32,
-    pub position: [f32; 3],
-    pub direction: [f32; 3],
+    pub position: Point3,
+    pub direction: Vec3,
+}
+
+impl Light {
+    // Spot lights only reach the positions inside their cone, the other lights reach everywhere
+    pub fn reaches(&self, position: Point3) -> bool {
+        return match self.mode {
+            LightMode::Spot(angle) => (position - self.position).normalize().dot(self.direction.normalize()) >= angle.to_radians().cos(),
+            _ => true,
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct Sphere {
     pub radius: f32,
-    pub center: [f32; 3],
-    pub color: [i32; 3],
+    pub center: Point3,
+    pub color: Color,
     pub specular: f32,
     pub reflective: f32,
+    pub texture: Option<Rc<Texture>>,
 }
 
 impl Sphere {
-    pub fn intersect_ray(&self, camera_origin: [f32; 3], ray_direction: [f32; 3]) -> (f32, f32) {
-        let distance_to_center = vector_subtraction(camera_origin, self.center);
+    pub fn intersect_ray(&self, camera_origin: Point3, ray_direction: Vec3) -> (f32, f32) {
+        let distance_to_center = camera_origin - self.center;
 
-        let a = dot_product(ray_direction, ray_direction);
-        let b = 2.0 * dot_product(distance_to_center, ray_direction);
-        let c = dot_product(distance_to_center, distance_to_center) - self.radius * self.radius;
+        let a = ray_direction.dot(ray_direction);
+        let b = 2.0 * distance_to_center.dot(ray_direction);
+        let c = distance_to_center.dot(distance_to_center) - self.radius * self.radius;
         let discriminant = b * b - 4.0 * a * c;
 
         if discriminant < 0.0 {
@@ -73,8 +87,124 @@ impl Sphere {
         return (t1, t2);
     }
 
-    pub fn get_normal(&self, position: [f32; 3]) -> [f32; 3] {
-        let normal_vector = vector_subtraction(position, self.center);
-        return divide_vector(normal_vector, vector_length(normal_vector));
+    pub fn get_normal(&self, position: Point3) -> Vec3 {
+        let normal_vector = position - self.center;
+        return normal_vector / normal_vector.length();
+    }
+
+    pub fn get_uv(&self, position: Point3) -> [f32; 2] {
+        let normal = self.get_normal(position);
+        let u = 0.5 + normal.z.atan2(normal.x) / (2.0 * PI);
+        let v = 0.5 - normal.y.clamp(-1.0, 1.0).asin() / PI;
+        return [u, v];
+    }
+
+    // Footprint is the world-space width of the pixel on the surface, used to pick the mip level
+    pub fn get_color(&self, position: Point3, footprint: f32) -> Color {
+        return match &self.texture {
+            Some(texture) => {
+                let u_footprint = footprint / (2.0 * PI * self.radius);
+                let v_footprint = footprint / (PI * self.radius);
+                let lod = texture.get_lod([u_footprint, 0.0], [0.0, v_footprint]);
+                texture.sample_lod(self.get_uv(position), lod)
+            },
+            None => self.color,
+        }
+    }
+}
+
+// World-space triangle for the raytracer, the normal follows the counter-clockwise winding of the mesh
+#[derive(Clone)]
+pub struct Facet {
+    pub vertices: [Point3; 3],
+    pub uvs: [[f32; 2]; 3],
+    pub color: Color,
+    pub specular: f32,
+    pub reflective: f32,
+    pub texture: Option<Rc<Texture>>,
+    // Corner normals of a curved surface, without them the facet is lit flat
+    pub normals: Option<[Vec3; 3]>,
+}
+
+impl Facet {
+    pub fn new(vertices: [Point3; 3], uvs: [[f32; 2]; 3], color: Color, texture: Option<Rc<Texture>>) -> Self {
+        Self { vertices, uvs, color, specular: 50.0, reflective: 0.0, texture, normals: None }
+    }
+
+    // Moller-Trumbore, returns the ray parameter or infinity when the ray misses
+    pub fn intersect_ray(&self, origin: Point3, direction: Vec3) -> f32 {
+        let [a, b, c] = self.vertices;
+        let edge_ab = b - a;
+        let edge_ac = c - a;
+        let p = direction.cross(edge_ac);
+        let determinant = edge_ab.dot(p);
+        if determinant.abs() < 1e-8 { return f32::INFINITY; }
+
+        let inverse_determinant = 1.0 / determinant;
+        let offset = origin - a;
+        let u = offset.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) { return f32::INFINITY; }
+
+        let q = offset.cross(edge_ab);
+        let v = direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 { return f32::INFINITY; }
+
+        return edge_ac.dot(q) * inverse_determinant;
+    }
+
+    pub fn get_normal(&self) -> Vec3 {
+        let [a, b, c] = self.vertices;
+        return (b - a).cross(c - a).normalize();
+    }
+
+    // Normal for lighting, blended from the corner normals when the facet has them
+    pub fn get_shading_normal(&self, position: Point3) -> Vec3 {
+        return match self.normals {
+            Some(normals) => {
+                let weights = self.get_barycentric(position);
+                (normals[0] * weights[0] + normals[1] * weights[1] + normals[2] * weights[2]).normalize()
+            },
+            None => self.get_normal(),
+        }
+    }
+
+    fn get_area(&self) -> f32 {
+        let [a, b, c] = self.vertices;
+        return (b - a).cross(c - a).length() / 2.0;
+    }
+
+    fn get_barycentric(&self, position: Point3) -> [f32; 3] {
+        let [a, b, c] = self.vertices;
+        let normal = (b - a).cross(c - a);
+        let area = normal.dot(normal);
+        if area == 0.0 { return [1.0, 0.0, 0.0]; }
+
+        let weight_b = (position - a).cross(c - a).dot(normal) / area;
+        let weight_c = (b - a).cross(position - a).dot(normal) / area;
+        return [1.0 - weight_b - weight_c, weight_b, weight_c];
+    }
+
+    pub fn get_uv(&self, position: Point3) -> [f32; 2] {
+        let weights = self.get_barycentric(position);
+        let mut uv = [0.0, 0.0];
+        for (weight, vertex_uv) in weights.iter().zip(self.uvs.iter()) {
+            uv[0] += weight * vertex_uv[0];
+            uv[1] += weight * vertex_uv[1];
+        }
+        return uv;
+    }
+
+    // Footprint is the world-space width of the pixel on the surface, scaled into UV space by the area ratio
+    pub fn get_color(&self, position: Point3, footprint: f32) -> Color {
+        return match &self.texture {
+            Some(texture) => {
+                let [uv_a, uv_b, uv_c] = self.uvs;
+                let uv_area = ((uv_b[0] - uv_a[0]) * (uv_c[1] - uv_a[1]) - (uv_c[0] - uv_a[0]) * (uv_b[1] - uv_a[1])).abs() / 2.0;
+                let uv_footprint = footprint * (uv_area / self.get_area()).sqrt();
+                let lod = texture.get_lod([uv_footprint, 0.0], [0.0, uv_footprint]);
+                texture.sample_lod(self.get_uv(position), lod)
+            },
+            None => self.color,
+        }
     }
 }
@@ -1,8 +1,23 @@
 mod utilities;
+mod math;
 mod raytracer;
 mod geometry;
 mod rasterizer;
 mod projection;
+mod texture;
+mod scene;
+mod rotation;
+mod renderer;
+mod controls;
+mod frame;
+mod clipping;
+mod font;
+mod overlay;
+mod primitives;
+mod mesh;
+mod shadow;
+mod gbuffer;
+pub mod drawing;
 
 
 use wgpu::util::DeviceExt;
@@ -14,10 +29,20 @@ use winit::{
 };
 use winit::window::{Window, WindowId};
 use winit::event::WindowEvent;
+use std::time::{Duration, Instant};
 
 use geometry::{ Vertex };
-use raytracer::{ Raytracer, init_raytracer };
-use rasterizer::{ Rasterizer, init_rasterizer };
+use controls::CameraController;
+use overlay::Overlay;
+
+pub use frame::{ Frame, BACKGROUND };
+pub use raytracer::{ Raytracer, init_raytracer, init_raytracer_with_canvas };
+pub use rasterizer::{ Rasterizer, RenderMode, RenderPath, Traversal, LineMode, init_rasterizer, init_rasterizer_with_canvas };
+pub use renderer::{ Backend, Renderer, init_renderer };
+pub use mesh::Mesh;
+pub use shadow::ShadowSettings;
+pub use gbuffer::{ GBuffer, Sample, Channel };
+pub use math::{ Vec3, Point3, Color, Rgba, BlendMode };
 
 pub struct State {
     surface: wgpu::Surface,
@@ -29,13 +54,28 @@ pub struct State {
     num_vertices: u32,
     size: winit::dpi::PhysicalSize<u32>,
     window: Window,
+    renderer: Renderer,
+    camera_controller: CameraController,
+    last_frame: Instant,
+    overlay: Overlay,
+    overlay_buffer: wgpu::Buffer,
+    num_overlay_vertices: u32,
+    show_overlay: bool,
+    // Time of the last renderer pass, and the frames counted since the frame rate was last measured
+    render_time: Duration,
+    frames: u32,
+    frame_rate: f32,
+    frame_rate_timer: Instant,
 }
 
 impl State {
+    // How often the frame rate is measured and the overlay redrawn
+    const OVERLAY_REFRESH: Duration = Duration::from_millis(500);
+
     pub fn window(&self) -> &Window {
         &self.window
     }
-    async fn new(window: Window, vertices: &[Vertex]) -> Self {
+    async fn new(window: Window, mut renderer: Renderer, camera_controller: CameraController) -> Self {
         let size = window.inner_size();
         let instance_options = wgpu::InstanceDescriptor {
             backends: wgpu::Backends::all(),
@@ -99,6 +139,7 @@ impl State {
         };
         let color_target_state = wgpu::ColorTargetState {
             format: config.format,
+            // Translucency is blended on the CPU against what is already drawn, every point carries its final color
             blend: Some(wgpu::BlendState::REPLACE),
             write_mask: wgpu::ColorWrites::ALL
         };
@@ -132,15 +173,12 @@ impl State {
             multiview: None,
         };
         let render_pipeline = device.create_render_pipeline(render_pipeline_descriptor);
-        let vertex_buffer_descriptor = &wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        };
-        let vertex_buffer = device.create_buffer_init(vertex_buffer_descriptor);
+        let vertices = renderer.get_state();
+        let vertex_buffer = Self::create_vertex_buffer(&device, vertices);
         let num_vertices = vertices.len() as u32;
+        let overlay_buffer = Self::create_vertex_buffer(&device, &[]);
 
-        Self {
+        let mut state = Self {
             surface,
             device,
             queue,
@@ -150,14 +188,142 @@ impl State {
             num_vertices,
             size,
             window,
+            renderer,
+            camera_controller,
+            last_frame: Instant::now(),
+            overlay: Overlay::new(Rasterizer::CANVAS),
+            overlay_buffer,
+            num_overlay_vertices: 0,
+            show_overlay: true,
+            render_time: Duration::ZERO,
+            frames: 0,
+            frame_rate: 0.0,
+            frame_rate_timer: Instant::now(),
+        };
+        state.refresh_overlay();
+        state
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, vertices: &[Vertex]) -> wgpu::Buffer {
+        let vertex_buffer_descriptor = &wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        };
+        device.create_buffer_init(vertex_buffer_descriptor)
+    }
+
+    // Renders the scene again on the CPU and replaces the points drawn by wgpu
+    fn upload_frame(&mut self) {
+        let start = Instant::now();
+        self.renderer.pass();
+        self.render_time = start.elapsed();
+
+        let vertices = self.renderer.get_state();
+        self.vertex_buffer = Self::create_vertex_buffer(&self.device, vertices);
+        self.num_vertices = vertices.len() as u32;
+        self.refresh_overlay();
+    }
+
+    // Rebuilds the overlay text and uploads its points, or empties it while the overlay is hidden
+    fn refresh_overlay(&mut self) {
+        if self.show_overlay {
+            let lines = self.overlay_lines();
+            self.overlay.set_lines(&lines);
+        } else {
+            self.overlay.clear();
+        }
+        let vertices = self.overlay.get_state();
+        self.overlay_buffer = Self::create_vertex_buffer(&self.device, vertices);
+        self.num_overlay_vertices = vertices.len() as u32;
+    }
+
+    fn overlay_lines(&mut self) -> Vec<String> {
+        let position = self.renderer.get_camera_mut().get_position();
+        let (renderer, work) = match self.renderer.get_backend() {
+            Backend::Rasterizer => (
+                format!(
+                    "Rasterizer {:?} {:?} {:?}{}",
+                    self.renderer.get_mode(),
+                    self.renderer.get_render_path(),
+                    self.renderer.get_traversal(),
+                    if self.renderer.get_shadows().is_some() { " Shadows" } else { "" },
+                ),
+                format!("Triangles {}", self.renderer.get_triangle_count()),
+            ),
+            Backend::Raytracer => (String::from("Raytracer"), format!("Rays {}", self.renderer.get_ray_count())),
+        };
+
+        return vec![
+            format!("FPS {:.1}", self.frame_rate),
+            format!("Render {:.1} ms", self.render_time.as_secs_f32() * 1000.0),
+            renderer,
+            format!("Camera {:?} {:.2} {:.2} {:.2}", self.renderer.get_projection_mode(), position.x, position.y, position.z),
+            work,
+        ];
+    }
+
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(VirtualKeyCode::P),
+                ..
+            },
+            ..
+        } = event {
+            self.save_screenshot();
+            return true;
+        }
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(VirtualKeyCode::B),
+                ..
+            },
+            ..
+        } = event {
+            self.save_gbuffer();
+            return true;
+        }
+        self.camera_controller.process_event(event)
+    }
+
+    // Writes the frame on screen next to where the viewer was started from
+    fn save_screenshot(&mut self) {
+        let path = frame::screenshot_path(".");
+        match self.renderer.save_frame(&path) {
+            Ok(_) => log::info!("Saved screenshot to {}", path.display()),
+            Err(e) => eprintln!("Could not save screenshot to {}: {}", path.display(), e),
         }
     }
 
-    fn input(&mut self, _event: &WindowEvent) -> bool {
-        false
+    // Writes an image of every G-buffer channel next to where the viewer was started from
+    fn save_gbuffer(&mut self) {
+        match self.renderer.save_gbuffer(".") {
+            Ok(paths) => paths.iter().for_each(|path| log::info!("Saved G-buffer channel to {}", path.display())),
+            Err(e) => eprintln!("Could not save the G-buffer: {}", e),
+        }
     }
 
-    fn update(&mut self) {}
+    fn update(&mut self) {
+        let now = Instant::now();
+        let frame_time = now - self.last_frame;
+        self.last_frame = now;
+
+        if self.camera_controller.update(self.renderer.get_camera_mut(), frame_time) {
+            self.upload_frame();
+        }
+
+        self.frames += 1;
+        let elapsed = now - self.frame_rate_timer;
+        if elapsed >= Self::OVERLAY_REFRESH {
+            self.frame_rate = self.frames as f32 / elapsed.as_secs_f32();
+            self.frames = 0;
+            self.frame_rate_timer = now;
+            self.refresh_overlay();
+        }
+    }
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         let output = self.surface.get_current_texture()?;
@@ -169,10 +335,11 @@ impl State {
         let mut encoder = self.device.create_command_encoder(encoder_options);
 
         {
+            let [r, g, b] = frame::BACKGROUND.to_normalized();
             let bg_color = wgpu::Color {
-                r: 0.1,
-                g: 0.2,
-                b: 0.3,
+                r: r as f64,
+                g: g as f64,
+                b: b as f64,
                 a: 1.0,
             };
             let pass_color_attachment = wgpu::RenderPassColorAttachment {
@@ -195,6 +362,12 @@ impl State {
             render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.draw(0..self.num_vertices, 0..1);
+
+            // Same pipeline, drawn last so the text stays on top of the frame
+            if self.num_overlay_vertices > 0 {
+                render_pass.set_vertex_buffer(0, self.overlay_buffer.slice(..));
+                render_pass.draw(0..self.num_overlay_vertices, 0..1);
+            }
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
@@ -224,13 +397,10 @@ pub async fn run() {
         .build(&event_loop)
         .unwrap();
 
-    // let mut raytracer = init_raytracer();
-    // let vertices = raytracer.get_state();
-    // let mut state = State::new(window, vertices).await;
+    let mut renderer = init_renderer();
+    let camera_controller = CameraController::new(renderer.get_camera_mut(), 7.0);
 
-    let mut rasterizer = init_rasterizer();
-    let vertices = rasterizer.get_state();
-    let mut state = State::new(window, vertices).await;
+    let mut state = State::new(window, renderer, camera_controller).await;
 
     event_loop.run(move |event, _, control_flow|
         match event {
@@ -273,6 +443,17 @@ fn handle_window(state: &mut State, window_id: WindowId, control_flow: &mut Cont
             } => {
                 *control_flow = ControlFlow::Exit
             },
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => {
+                handle_shortcut(state, *key);
+            },
             WindowEvent::Resized(physical_size) => {
                 state.resize(*physical_size);
             }
@@ -283,3 +464,32 @@ fn handle_window(state: &mut State, window_id: WindowId, control_flow: &mut Cont
         }
     }
 }
+
+// R swaps the raytracer and the rasterizer and V switches both between perspective and orthographic
+// views. 1-3 pick the rasterizer's wireframe, filled or shaded mode and T switches its triangle traversal
+// between scanline and edge functions. L turns anti-aliased wireframe lines on or off, [ and ] make the
+// lines thinner or wider. H turns the rasterizer's shadows on or off, G switches its shading between
+// forward and deferred and O shows or hides the overlay
+fn handle_shortcut(state: &mut State, key: VirtualKeyCode) {
+    if key == VirtualKeyCode::O {
+        state.show_overlay = !state.show_overlay;
+        state.refresh_overlay();
+        return;
+    }
+
+    match key {
+        VirtualKeyCode::R => state.renderer.toggle_backend(),
+        VirtualKeyCode::T => state.renderer.toggle_traversal(),
+        VirtualKeyCode::V => state.renderer.toggle_projection_mode(),
+        VirtualKeyCode::Key1 => state.renderer.set_mode(RenderMode::Wireframe),
+        VirtualKeyCode::Key2 => state.renderer.set_mode(RenderMode::Filled),
+        VirtualKeyCode::Key3 => state.renderer.set_mode(RenderMode::Shaded),
+        VirtualKeyCode::L => state.renderer.toggle_line_mode(),
+        VirtualKeyCode::H => state.renderer.toggle_shadows(),
+        VirtualKeyCode::G => state.renderer.toggle_render_path(),
+        VirtualKeyCode::LBracket => state.renderer.set_line_width((state.renderer.get_line_width() - 0.5).max(0.5)),
+        VirtualKeyCode::RBracket => state.renderer.set_line_width(state.renderer.get_line_width() + 0.5),
+        _ => return,
+    }
+    state.upload_frame();
+}
@@ -0,0 +1,587 @@
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+use crate::utilities::{
+    Axis,
+    dot_product,
+    vector_length,
+    vector_addition,
+    vector_subtraction,
+    vector_multiplication,
+    scale_vector,
+    divide_vector,
+    reverse_vector,
+    multiply_mat4_mat4,
+    multiply_mat4_vec,
+    to_translation_mat4,
+    to_scale_mat4,
+    to_rotation_mat4,
+    to_axis_rotation_mat4,
+    rotate_vector,
+    transpose_mat4,
+    determinant_mat4,
+    inverse_mat4,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        [self.x, self.y, self.z]
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        dot_product(self.to_array(), other.to_array())
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(self) -> f32 {
+        vector_length(self.to_array())
+    }
+
+    // Zero-length vectors are returned unchanged instead of turning into NaN
+    pub fn normalize(self) -> Vec3 {
+        let length = self.length();
+        if length == 0.0 { return self; }
+        self / length
+    }
+
+    pub fn to_vec4(self) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, 0.0)
+    }
+
+    // Angle in degrees around an axis of any length
+    pub fn rotate_around(self, axis: Vec3, angle: f32) -> Vec3 {
+        Vec3::from(rotate_vector(self.to_array(), axis.to_array(), angle))
+    }
+}
+
+impl From<[f32; 3]> for Vec3 {
+    fn from(array: [f32; 3]) -> Self {
+        Vec3::new(array[0], array[1], array[2])
+    }
+}
+
+impl From<Vec3> for [f32; 3] {
+    fn from(vector: Vec3) -> Self {
+        vector.to_array()
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::from(vector_addition(self.to_array(), other.to_array()))
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, other: Vec3) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::from(vector_subtraction(self.to_array(), other.to_array()))
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+    fn mul(self, scalar: f32) -> Vec3 {
+        Vec3::from(scale_vector(self.to_array(), scalar))
+    }
+}
+
+impl Mul<Vec3> for f32 {
+    type Output = Vec3;
+    fn mul(self, vector: Vec3) -> Vec3 {
+        vector * self
+    }
+}
+
+// Component-wise product, used for non-uniform scale
+impl Mul<Vec3> for Vec3 {
+    type Output = Vec3;
+    fn mul(self, other: Vec3) -> Vec3 {
+        Vec3::from(vector_multiplication(self.to_array(), other.to_array()))
+    }
+}
+
+impl Div<f32> for Vec3 {
+    type Output = Vec3;
+    fn div(self, scalar: f32) -> Vec3 {
+        Vec3::from(divide_vector(self.to_array(), scalar))
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+    fn neg(self) -> Vec3 {
+        Vec3::from(reverse_vector(self.to_array()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Point3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Point3 {
+    pub const ORIGIN: Point3 = Point3::new(0.0, 0.0, 0.0);
+
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        [self.x, self.y, self.z]
+    }
+
+    pub fn to_vec3(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    pub fn to_vec4(self) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, 1.0)
+    }
+}
+
+impl From<[f32; 3]> for Point3 {
+    fn from(array: [f32; 3]) -> Self {
+        Point3::new(array[0], array[1], array[2])
+    }
+}
+
+impl From<Point3> for [f32; 3] {
+    fn from(point: Point3) -> Self {
+        point.to_array()
+    }
+}
+
+impl Add<Vec3> for Point3 {
+    type Output = Point3;
+    fn add(self, offset: Vec3) -> Point3 {
+        Point3::from(vector_addition(self.to_array(), offset.to_array()))
+    }
+}
+
+impl Sub<Vec3> for Point3 {
+    type Output = Point3;
+    fn sub(self, offset: Vec3) -> Point3 {
+        Point3::from(vector_subtraction(self.to_array(), offset.to_array()))
+    }
+}
+
+impl Sub for Point3 {
+    type Output = Vec3;
+    fn sub(self, other: Point3) -> Vec3 {
+        Vec3::from(vector_subtraction(self.to_array(), other.to_array()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Vec4 {
+    pub const fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self { x, y, z, w }
+    }
+
+    pub fn to_array(self) -> [f32; 4] {
+        [self.x, self.y, self.z, self.w]
+    }
+
+    pub fn dot(self, other: Vec4) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn xyz(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    pub fn to_point3(self) -> Point3 {
+        Point3::new(self.x, self.y, self.z)
+    }
+}
+
+impl From<[f32; 4]> for Vec4 {
+    fn from(array: [f32; 4]) -> Self {
+        Vec4::new(array[0], array[1], array[2], array[3])
+    }
+}
+
+impl From<Vec4> for [f32; 4] {
+    fn from(vector: Vec4) -> Self {
+        vector.to_array()
+    }
+}
+
+impl Add for Vec4 {
+    type Output = Vec4;
+    fn add(self, other: Vec4) -> Vec4 {
+        Vec4::new(self.x + other.x, self.y + other.y, self.z + other.z, self.w + other.w)
+    }
+}
+
+impl Sub for Vec4 {
+    type Output = Vec4;
+    fn sub(self, other: Vec4) -> Vec4 {
+        Vec4::new(self.x - other.x, self.y - other.y, self.z - other.z, self.w - other.w)
+    }
+}
+
+impl Mul<f32> for Vec4 {
+    type Output = Vec4;
+    fn mul(self, scalar: f32) -> Vec4 {
+        Vec4::new(self.x * scalar, self.y * scalar, self.z * scalar, self.w * scalar)
+    }
+}
+
+impl Neg for Vec4 {
+    type Output = Vec4;
+    fn neg(self) -> Vec4 {
+        Vec4::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+// Row-major 4x4 matrix acting on column vectors, same layout as the utilities arrays
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4(pub [[f32; 4]; 4]);
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Mat4::IDENTITY
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub fn translation(offset: Vec3) -> Self {
+        Mat4(to_translation_mat4(offset.to_array()))
+    }
+
+    pub fn scale(scale: Vec3) -> Self {
+        Mat4(to_scale_mat4(scale.to_array()))
+    }
+
+    pub fn rotation(axis: Axis, radian: f32) -> Self {
+        Mat4(to_rotation_mat4(axis, radian))
+    }
+
+    pub fn axis_rotation(axis: Vec3, radian: f32) -> Self {
+        Mat4(to_axis_rotation_mat4(axis.to_array(), radian))
+    }
+
+    pub fn to_array(self) -> [[f32; 4]; 4] {
+        self.0
+    }
+
+    pub fn transpose(self) -> Mat4 {
+        Mat4(transpose_mat4(self.0))
+    }
+
+    pub fn determinant(self) -> f32 {
+        determinant_mat4(self.0)
+    }
+
+    // None for singular matrices, e.g. a model matrix with a zero scale axis
+    pub fn inverse(self) -> Option<Mat4> {
+        inverse_mat4(self.0).map(Mat4)
+    }
+
+    // Inverse-transpose of the upper 3x3, keeps normals perpendicular to surfaces under non-uniform scale
+    pub fn normal_matrix(self) -> Option<Mat4> {
+        let mut linear = self.0;
+        for row in linear.iter_mut().take(3) {
+            row[3] = 0.0;
+        }
+        linear[3] = [0.0, 0.0, 0.0, 1.0];
+        Mat4(linear).inverse().map(Mat4::transpose)
+    }
+
+    pub fn transform_normal(self, normal: Vec3) -> Option<Vec3> {
+        self.normal_matrix().map(|matrix| matrix.transform_vector(normal).normalize())
+    }
+
+    pub fn transform_point(self, point: Point3) -> Point3 {
+        (self * point.to_vec4()).to_point3()
+    }
+
+    pub fn transform_vector(self, vector: Vec3) -> Vec3 {
+        (self * vector.to_vec4()).xyz()
+    }
+}
+
+impl From<[[f32; 4]; 4]> for Mat4 {
+    fn from(array: [[f32; 4]; 4]) -> Self {
+        Mat4(array)
+    }
+}
+
+impl From<Mat4> for [[f32; 4]; 4] {
+    fn from(matrix: Mat4) -> Self {
+        matrix.0
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        Mat4(multiply_mat4_mat4(self.0, other.0))
+    }
+}
+
+impl Mul<Vec4> for Mat4 {
+    type Output = Vec4;
+    fn mul(self, vector: Vec4) -> Vec4 {
+        Vec4::from(multiply_mat4_vec(self.0, vector.to_array()))
+    }
+}
+
+// Colors keep the 0-255 channel range used throughout the renderers
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::new(255.0, 255.0, 255.0);
+    pub const RED: Color = Color::new(255.0, 0.0, 0.0);
+    pub const GREEN: Color = Color::new(0.0, 255.0, 0.0);
+    pub const BLUE: Color = Color::new(0.0, 0.0, 255.0);
+    pub const PURPLE: Color = Color::new(255.0, 0.0, 255.0);
+    pub const YELLOW: Color = Color::new(255.0, 255.0, 0.0);
+    pub const CYAN: Color = Color::new(0.0, 255.0, 255.0);
+
+    pub const fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        [self.r, self.g, self.b]
+    }
+
+    pub fn clamp(self) -> Color {
+        Color::new(self.r.clamp(0.0, 255.0), self.g.clamp(0.0, 255.0), self.b.clamp(0.0, 255.0))
+    }
+
+    // Channels mapped to 0-1 for the GPU vertex buffer
+    pub fn to_normalized(self) -> [f32; 3] {
+        [self.r / 255.0, self.g / 255.0, self.b / 255.0]
+    }
+
+    pub fn lerp(self, other: Color, t: f32) -> Color {
+        self + (other - self) * t
+    }
+}
+
+// Color with an alpha channel for blending, alpha runs from 0 for invisible to 1 for opaque
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgba {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Rgba {
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    pub const fn from_color(color: Color, alpha: f32) -> Self {
+        Self { r: color.r, g: color.g, b: color.b, a: alpha }
+    }
+
+    pub const fn opaque(color: Color) -> Self {
+        Self::from_color(color, 1.0)
+    }
+
+    pub fn to_color(self) -> Color {
+        Color::new(self.r, self.g, self.b)
+    }
+
+    pub fn is_opaque(self) -> bool {
+        self.a >= 1.0
+    }
+}
+
+impl From<Color> for Rgba {
+    fn from(color: Color) -> Self {
+        Rgba::opaque(color)
+    }
+}
+
+// How a color with alpha combines with the color already on the canvas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    // Painted over the destination by its alpha
+    Over,
+    // Added to the destination, for glows and light
+    Additive,
+    // Darkens the destination like a filter, for tinted glass
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn blend(self, destination: Color, source: Rgba) -> Color {
+        let alpha = source.a.clamp(0.0, 1.0);
+        let color = source.to_color();
+        match self {
+            BlendMode::Over => destination.lerp(color, alpha),
+            BlendMode::Additive => (destination + color * alpha).clamp(),
+            BlendMode::Multiply => destination.lerp(destination * color, alpha),
+        }
+    }
+}
+
+impl From<[f32; 3]> for Color {
+    fn from(array: [f32; 3]) -> Self {
+        Color::new(array[0], array[1], array[2])
+    }
+}
+
+impl From<[i32; 3]> for Color {
+    fn from(array: [i32; 3]) -> Self {
+        Color::new(array[0] as f32, array[1] as f32, array[2] as f32)
+    }
+}
+
+impl From<Color> for [f32; 3] {
+    fn from(color: Color) -> Self {
+        color.to_array()
+    }
+}
+
+impl Add for Color {
+    type Output = Color;
+    fn add(self, other: Color) -> Color {
+        Color::new(self.r + other.r, self.g + other.g, self.b + other.b)
+    }
+}
+
+impl AddAssign for Color {
+    fn add_assign(&mut self, other: Color) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Color {
+    type Output = Color;
+    fn sub(self, other: Color) -> Color {
+        Color::new(self.r - other.r, self.g - other.g, self.b - other.b)
+    }
+}
+
+impl Mul<f32> for Color {
+    type Output = Color;
+    fn mul(self, factor: f32) -> Color {
+        Color::new(self.r * factor, self.g * factor, self.b * factor)
+    }
+}
+
+// Modulation in 0-255 space, e.g. texture color times a tint
+impl Mul<Color> for Color {
+    type Output = Color;
+    fn mul(self, other: Color) -> Color {
+        Color::new(self.r * other.r / 255.0, self.g * other.g / 255.0, self.b * other.b / 255.0)
+    }
+}
+
+impl Div<f32> for Color {
+    type Output = Color;
+    fn div(self, divisor: f32) -> Color {
+        Color::new(self.r / divisor, self.g / divisor, self.b / divisor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESTINATION: Color = Color::new(100.0, 200.0, 50.0);
+    const SOURCE: Color = Color::new(200.0, 100.0, 250.0);
+
+    fn assert_color(actual: Color, expected: Color) {
+        let difference = actual - expected;
+        assert!(difference.r.abs() < 1e-3 && difference.g.abs() < 1e-3 && difference.b.abs() < 1e-3, "{:?} is not {:?}", actual, expected);
+    }
+
+    // Each mode leaves the destination alone at alpha 0 and applies the source fully at alpha 1
+    #[test]
+    fn blend_modes_scale_with_alpha() {
+        let blend = |mode: BlendMode, alpha: f32| mode.blend(DESTINATION, Rgba::from_color(SOURCE, alpha));
+        let multiplied = Color::new(100.0 * 200.0 / 255.0, 200.0 * 100.0 / 255.0, 50.0 * 250.0 / 255.0);
+
+        assert_color(blend(BlendMode::Over, 0.0), DESTINATION);
+        assert_color(blend(BlendMode::Over, 0.5), Color::new(150.0, 150.0, 150.0));
+        assert_color(blend(BlendMode::Over, 1.0), SOURCE);
+
+        assert_color(blend(BlendMode::Additive, 0.0), DESTINATION);
+        assert_color(blend(BlendMode::Additive, 0.5), Color::new(200.0, 250.0, 175.0));
+        assert_color(blend(BlendMode::Additive, 1.0), Color::WHITE);
+
+        assert_color(blend(BlendMode::Multiply, 0.0), DESTINATION);
+        assert_color(blend(BlendMode::Multiply, 0.5), (DESTINATION + multiplied) * 0.5);
+        assert_color(blend(BlendMode::Multiply, 1.0), multiplied);
+    }
+
+    #[test]
+    fn additive_blending_clamps_to_the_channel_range() {
+        assert_color(BlendMode::Additive.blend(DESTINATION, Rgba::opaque(SOURCE)), Color::WHITE);
+        assert_color(BlendMode::Additive.blend(Color::new(250.0, 0.0, 0.0), Rgba::from_color(Color::new(20.0, 20.0, 0.0), 0.5)), Color::new(255.0, 10.0, 0.0));
+        // Alpha above one counts as opaque instead of adding more than the source
+        assert_color(BlendMode::Additive.blend(Color::BLACK, Rgba::from_color(Color::new(100.0, 0.0, 0.0), 2.0)), Color::new(100.0, 0.0, 0.0));
+    }
+
+    // Tangents move with the surface, the normal matrix keeps the normal perpendicular to them where
+    // transforming the normal like a vector would tilt it
+    #[test]
+    fn normals_stay_perpendicular_to_tangents_under_non_uniform_scale() {
+        let transform = Mat4::axis_rotation(Vec3::new(1.0, 2.0, 3.0).normalize(), 0.6) * Mat4::scale(Vec3::new(1.0, 4.0, 0.5));
+        let normal = Vec3::new(1.0, 1.0, 1.0).normalize();
+        let tangents = [Vec3::new(1.0, -1.0, 0.0), Vec3::new(0.0, 1.0, -1.0)];
+
+        let transformed = transform.transform_normal(normal).unwrap();
+        assert!((transformed.length() - 1.0).abs() < 1e-5);
+        for tangent in tangents {
+            assert!(tangent.dot(normal).abs() < 1e-6);
+            let moved = transform.transform_vector(tangent).normalize();
+            assert!(transformed.dot(moved).abs() < 1e-5, "{:?} is not perpendicular to {:?}", transformed, moved);
+            assert!(transform.transform_vector(normal).normalize().dot(moved).abs() > 0.1);
+        }
+    }
+}
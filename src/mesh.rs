@@ -0,0 +1,341 @@
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use crate::geometry::Facet;
+use crate::math::{Vec3, Point3, Mat4, Color, Rgba, BlendMode};
+use crate::texture::Texture;
+
+// Indexed triangle mesh. Vertices sit in one buffer shared by every triangle that uses them, and the
+// index buffer picks three corners per triangle, counter-clockwise seen from the front. Normals, UVs
+// and colors are optional and come one per vertex, so a vertex on a hard edge or a UV seam is stored
+// once for each side
+pub struct Mesh {
+    vertices: Vec<Point3>,
+    indices: Vec<[usize; 3]>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<[f32; 2]>>,
+    colors: Option<Vec<Rgba>>,
+    // Used for every vertex when the mesh has no colors of its own
+    color: Rgba,
+    texture: Option<Rc<Texture>>,
+    blend_mode: BlendMode,
+    // Specular exponent for the renderers that draw highlights, zero or below for a matte surface
+    specular: f32,
+    // A singular transform is reported the first time it is met instead of on every pass
+    singular_warned: Cell<bool>,
+}
+
+// Why a mesh was rejected, with the counts that did not line up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MeshError {
+    IndexOutOfRange { triangle: usize, index: usize, vertex_count: usize },
+    AttributeLength { attribute: &'static str, length: usize, vertex_count: usize },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::IndexOutOfRange { triangle, index, vertex_count } => {
+                write!(f, "triangle {} uses vertex {} but the mesh has {} vertices", triangle, index, vertex_count)
+            },
+            MeshError::AttributeLength { attribute, length, vertex_count } => {
+                write!(f, "{} {} given for {} vertices", length, attribute, vertex_count)
+            },
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+impl Mesh {
+    // Fails when a triangle points past the end of the vertex buffer
+    pub fn new(vertices: Vec<Point3>, indices: Vec<[usize; 3]>) -> Result<Self, MeshError> {
+        if let Some((triangle, &index)) = indices
+            .iter()
+            .enumerate()
+            .find_map(|(triangle, group)| group.iter().find(|&&index| index >= vertices.len()).map(|index| (triangle, index)))
+        {
+            return Err(MeshError::IndexOutOfRange { triangle, index, vertex_count: vertices.len() });
+        }
+
+        return Ok(Self {
+            vertices, indices, normals: None, uvs: None, colors: None,
+            color: Rgba::opaque(Color::WHITE), texture: None, blend_mode: BlendMode::Over, specular: 50.0,
+            singular_warned: Cell::new(false),
+        });
+    }
+
+    fn check_length(&self, attribute: &'static str, length: usize) -> Result<(), MeshError> {
+        if length != self.vertices.len() {
+            return Err(MeshError::AttributeLength { attribute, length, vertex_count: self.vertices.len() });
+        }
+        return Ok(());
+    }
+
+    pub fn get_vertices(&self) -> &[Point3] {
+        return &self.vertices;
+    }
+
+    pub fn get_indices(&self) -> &[[usize; 3]] {
+        return &self.indices;
+    }
+
+    pub fn get_normals(&self) -> Option<&[Vec3]> {
+        return self.normals.as_deref();
+    }
+
+    pub fn set_normals(&mut self, normals: Vec<Vec3>) -> Result<(), MeshError> {
+        self.check_length("normals", normals.len())?;
+        self.normals = Some(normals);
+        return Ok(());
+    }
+
+    pub fn get_uvs(&self) -> Option<&[[f32; 2]]> {
+        return self.uvs.as_deref();
+    }
+
+    pub fn set_uvs(&mut self, uvs: Vec<[f32; 2]>) -> Result<(), MeshError> {
+        self.check_length("uvs", uvs.len())?;
+        self.uvs = Some(uvs);
+        return Ok(());
+    }
+
+    pub fn get_colors(&self) -> Option<&[Rgba]> {
+        return self.colors.as_deref();
+    }
+
+    pub fn set_colors(&mut self, colors: Vec<Rgba>) -> Result<(), MeshError> {
+        self.check_length("colors", colors.len())?;
+        self.colors = Some(colors);
+        return Ok(());
+    }
+
+    pub fn get_color(&self) -> Rgba {
+        return self.color;
+    }
+
+    pub fn set_color(&mut self, color: impl Into<Rgba>) {
+        self.color = color.into();
+    }
+
+    pub fn get_vertex_color(&self, index: usize) -> Rgba {
+        return match &self.colors {
+            Some(colors) => colors[index],
+            None => self.color,
+        }
+    }
+
+    pub fn get_vertex_uv(&self, index: usize) -> [f32; 2] {
+        return match &self.uvs {
+            Some(uvs) => uvs[index],
+            None => [0.0, 0.0],
+        }
+    }
+
+    pub fn get_texture(&self) -> Option<&Rc<Texture>> {
+        return self.texture.as_ref();
+    }
+
+    pub fn set_texture(&mut self, texture: Rc<Texture>) {
+        self.texture = Some(texture);
+    }
+
+    pub fn get_blend_mode(&self) -> BlendMode {
+        return self.blend_mode;
+    }
+
+    // Any mode other than Over draws the whole mesh with the translucent triangles
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    pub fn get_specular(&self) -> f32 {
+        return self.specular;
+    }
+
+    pub fn set_specular(&mut self, specular: f32) {
+        self.specular = specular;
+    }
+
+    // Sets the alpha of the mesh color and of every vertex color
+    pub fn set_opacity(&mut self, alpha: f32) {
+        self.color.a = alpha;
+        for color in self.colors.iter_mut().flatten() {
+            color.a = alpha;
+        }
+    }
+
+    pub fn is_translucent(&self, group: [usize; 3]) -> bool {
+        return self.blend_mode != BlendMode::Over || group.iter().any(|&index| !self.get_vertex_color(index).is_opaque());
+    }
+
+    // Cross product of two edges, its length is twice the triangle's area
+    fn get_area_normal(&self, group: [usize; 3]) -> Vec3 {
+        let [a, b, c] = group.map(|index| self.vertices[index]);
+        return (b - a).cross(c - a);
+    }
+
+    // Unit normal of every triangle from its winding, zero for triangles without area
+    pub fn get_face_normals(&self) -> Vec<Vec3> {
+        return self.indices.iter().map(|&group| self.get_area_normal(group).normalize()).collect();
+    }
+
+    // Smooth normals: each vertex takes the sum of the normals of the triangles around it weighted by
+    // their area, so slivers barely bend it. Vertices no triangle uses are left with a zero normal
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vec3::new(0.0, 0.0, 0.0); self.vertices.len()];
+        for &group in self.indices.iter() {
+            let area_normal = self.get_area_normal(group);
+            for index in group {
+                normals[index] += area_normal;
+            }
+        }
+        self.normals = Some(normals.into_iter().map(Vec3::normalize).collect());
+    }
+
+    // Smallest and largest corner of the axis aligned box around the vertices, None for an empty mesh
+    pub fn get_bounds(&self) -> Option<[Point3; 2]> {
+        let first = *self.vertices.first()?;
+        return Some(self.vertices.iter().fold([first, first], |[min, max], vertex| [
+            Point3::new(min.x.min(vertex.x), min.y.min(vertex.y), min.z.min(vertex.z)),
+            Point3::new(max.x.max(vertex.x), max.y.max(vertex.y), max.z.max(vertex.z)),
+        ]));
+    }
+
+    // Vertex normals under a model transform. A singular transform flattens the mesh and has no normal
+    // matrix, the normals are then left untransformed rather than losing the mesh
+    pub fn get_transformed_normals(&self, transform: Mat4) -> Option<Vec<Vec3>> {
+        let normals = self.normals.as_ref()?;
+        let normal_matrix = transform.normal_matrix().unwrap_or_else(|| {
+            if !self.singular_warned.replace(true) {
+                log::warn!("Mesh transform is singular, its normals are not transformed");
+            }
+            Mat4::IDENTITY
+        });
+        return Some(normals.iter().map(|&normal| normal_matrix.transform_vector(normal).normalize()).collect());
+    }
+
+    // World-space triangles for renderers that do not rasterize, e.g. the raytracer. A facet has a single
+    // color, the mean of its corners
+    pub fn get_facets(&self, transform: Mat4) -> Vec<Facet> {
+        let normals = self.get_transformed_normals(transform);
+        self.indices
+            .iter()
+            .map(|&group| {
+                let vertices = group.map(|index| transform.transform_point(self.vertices[index]));
+                let uvs = group.map(|index| self.get_vertex_uv(index));
+                let color = group.iter().fold(Color::BLACK, |sum, &index| sum + self.get_vertex_color(index).to_color()) / 3.0;
+                let mut facet = Facet::new(vertices, uvs, color, self.texture.clone());
+                facet.specular = self.specular;
+                facet.normals = normals.as_ref().map(|normals| group.map(|index| normals[index]));
+                facet
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn tetrahedron() -> Mesh {
+        let vertices = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Point3::new(0.0, 0.0, 1.0),
+        ];
+        return Mesh::new(vertices, vec![[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]]).unwrap();
+    }
+
+    fn point() -> impl Strategy<Value = Point3> {
+        [-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0].prop_map(Point3::from)
+    }
+
+    proptest! {
+        // Moving the mesh moves its bounds with it, and every vertex stays inside them
+        #[test]
+        fn bounds_hold_every_vertex(vertices in prop::collection::vec(point(), 1..20), offset in point()) {
+            let mesh = Mesh::new(vertices.clone(), vec![]).unwrap();
+            let [min, max] = mesh.get_bounds().unwrap();
+            for vertex in vertices.iter() {
+                prop_assert!(vertex.x >= min.x && vertex.y >= min.y && vertex.z >= min.z);
+                prop_assert!(vertex.x <= max.x && vertex.y <= max.y && vertex.z <= max.z);
+            }
+
+            let moved = Mesh::new(vertices.iter().map(|&vertex| vertex + offset.to_vec3()).collect(), vec![]).unwrap();
+            let [moved_min, moved_max] = moved.get_bounds().unwrap();
+            prop_assert!((moved_min - (min + offset.to_vec3())).length() < 1e-4);
+            prop_assert!((moved_max - (max + offset.to_vec3())).length() < 1e-4);
+        }
+
+        // Splitting a triangle into two halves must not change the normal at its corners
+        #[test]
+        fn vertex_normals_weigh_triangles_by_area(a in point(), b in point(), c in point(), t in 0.1f32..0.9) {
+            prop_assume!((b - a).cross(c - a).length() > 1e-2);
+            let split = b + (c - b) * t;
+            let mut mesh = Mesh::new(vec![a, b, c, split], vec![[0, 1, 3], [0, 3, 2]]).unwrap();
+            mesh.compute_normals();
+
+            let expected = (b - a).cross(c - a).normalize();
+            for normal in mesh.get_normals().unwrap() {
+                prop_assert!((*normal - expected).length() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range_indices_are_rejected() {
+        let vertices = vec![Point3::ORIGIN, Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+        let error = Mesh::new(vertices.clone(), vec![[0, 1, 2], [2, 1, 3]]).err().unwrap();
+        assert_eq!(error, MeshError::IndexOutOfRange { triangle: 1, index: 3, vertex_count: 3 });
+        assert!(error.to_string().contains("triangle 1 uses vertex 3"));
+
+        let mut mesh = Mesh::new(vertices, vec![[0, 1, 2]]).unwrap();
+        assert_eq!(mesh.set_normals(vec![Vec3::new(0.0, 0.0, 1.0); 2]), Err(MeshError::AttributeLength { attribute: "normals", length: 2, vertex_count: 3 }));
+        assert!(mesh.set_uvs(vec![[0.0, 0.0]; 4]).is_err());
+        assert!(mesh.set_colors(vec![Rgba::opaque(Color::RED); 3]).is_ok());
+        assert!(mesh.get_normals().is_none() && mesh.get_uvs().is_none());
+    }
+
+    #[test]
+    fn face_normals_follow_the_winding() {
+        let mesh = tetrahedron();
+        let normals = mesh.get_face_normals();
+        assert_eq!(normals[0], Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(normals[1], Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(normals[2], Vec3::new(-1.0, 0.0, 0.0));
+        let slanted = 1.0 / 3.0f32.sqrt();
+        assert!((normals[3] - Vec3::new(slanted, slanted, slanted)).length() < 1e-6);
+    }
+
+    // The corner at the origin meets three faces of the same area, its normal points evenly between them
+    #[test]
+    fn smooth_normals_average_the_faces_around_a_vertex() {
+        let mut mesh = tetrahedron();
+        mesh.compute_normals();
+        let normals = mesh.get_normals().unwrap();
+        let slanted = 1.0 / 3.0f32.sqrt();
+        assert!((normals[0] - Vec3::new(-slanted, -slanted, -slanted)).length() < 1e-6);
+        assert!(normals.iter().all(|normal| (normal.length() - 1.0).abs() < 1e-6));
+    }
+
+    // Non-uniform scale bends normals, a transform without an inverse leaves them as they are
+    #[test]
+    fn transformed_normals_survive_singular_transforms() {
+        let mut mesh = tetrahedron();
+        mesh.compute_normals();
+        let local = mesh.get_normals().unwrap().to_vec();
+
+        let stretched = mesh.get_transformed_normals(Mat4::scale(Vec3::new(2.0, 1.0, 1.0))).unwrap();
+        let expected = Vec3::new(local[0].x / 2.0, local[0].y, local[0].z).normalize();
+        assert!((stretched[0] - expected).length() < 1e-6);
+
+        assert!(!mesh.singular_warned.get());
+        let flattened = mesh.get_transformed_normals(Mat4::scale(Vec3::new(1.0, 0.0, 1.0))).unwrap();
+        assert!(mesh.singular_warned.get());
+        assert!(flattened.iter().zip(local.iter()).all(|(&flat, &normal)| (flat - normal).length() < 1e-6));
+        assert_eq!(mesh.get_facets(Mat4::scale(Vec3::new(1.0, 0.0, 1.0))).len(), 4);
+    }
+}
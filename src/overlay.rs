@@ -0,0 +1,53 @@
+use crate::drawing::{self, Canvas};
+use crate::geometry::Vertex;
+use crate::math::Color;
+
+// Text drawn over the renderer's frame in the viewer. It keeps its own points, so it can change every
+// frame without rendering the scene again
+pub struct Overlay {
+    state: Vec<Vertex>,
+    canvas: [i32; 2],
+}
+
+impl Overlay {
+    const SCALE: i32 = 3;
+    const MARGIN: i32 = 16;
+    const TEXT_COLOR: Color = Color::WHITE;
+    // Offset copy behind the text keeps it readable over bright parts of the frame
+    const SHADOW_COLOR: Color = Color::BLACK;
+
+    pub fn new(canvas: [i32; 2]) -> Self {
+        Self { state: vec![], canvas }
+    }
+
+    pub fn get_state(&self) -> &[Vertex] {
+        return self.state.as_slice();
+    }
+
+    pub fn clear(&mut self) {
+        self.state.clear();
+    }
+
+    // Replaces the overlay with these lines in the top left corner
+    pub fn set_lines(&mut self, lines: &[String]) {
+        self.state.clear();
+        let text = lines.join("\n");
+        let position = [-self.canvas[0] / 2 + Self::MARGIN, self.canvas[1] / 2 - Self::MARGIN];
+
+        let shadow = [position[0] + Self::SCALE / 2 + 1, position[1] - Self::SCALE / 2 - 1];
+        drawing::draw_text(self, shadow, &text, Self::SCALE, Self::SHADOW_COLOR);
+        drawing::draw_text(self, position, &text, Self::SCALE, Self::TEXT_COLOR);
+    }
+}
+
+impl Canvas for Overlay {
+    fn get_canvas_size(&self) -> [i32; 2] {
+        return self.canvas;
+    }
+
+    fn put_pixel(&mut self, x: i32, y: i32, rgb: Color) {
+        let x_cord = x as f32 / (self.canvas[0] / 2) as f32;
+        let y_cord = y as f32 / (self.canvas[1] / 2) as f32;
+        self.state.push(Vertex { position: [x_cord, y_cord, 0.0], color: rgb.to_normalized() });
+    }
+}
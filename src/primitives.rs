@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+use crate::math::{Vec3, Point3, Color};
+use crate::mesh::Mesh;
+
+// Meshes for the analytic shapes, centered on the origin with the y axis up. Every triangle winds
+// counter-clockwise seen from outside, and every vertex has a unit normal pointing out of the surface.
+// Vertices along UV seams and hard edges are doubled so each copy keeps its own UV and normal
+
+// Direction from the y axis at longitude u in [0, 1], laid out like Sphere::get_uv so textures line up
+// with the raytracer's spheres
+fn around_y(u: f32) -> Vec3 {
+    let angle = u * TAU;
+    return Vec3::new(-angle.cos(), 0.0, -angle.sin());
+}
+
+// Collects vertices with their normal and UV
+struct Builder {
+    vertices: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<[f32; 2]>,
+    groups: Vec<[usize; 3]>,
+}
+
+impl Builder {
+    fn new() -> Self {
+        Self { vertices: vec![], normals: vec![], uvs: vec![], groups: vec![] }
+    }
+
+    fn add_vertex(&mut self, position: Point3, normal: Vec3, uv: [f32; 2]) -> usize {
+        self.vertices.push(position);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+        return self.vertices.len() - 1;
+    }
+
+    fn add_triangle(&mut self, a: usize, b: usize, c: usize) {
+        self.groups.push([a, b, c]);
+    }
+
+    // Triangles for a grid of columns + 1 by rows + 1 vertices starting at first. Rows run downwards
+    // over the outside, so [top, bottom next, bottom] winds outwards
+    fn add_quads(&mut self, first: usize, columns: usize, rows: usize) {
+        let index = |row: usize, column: usize| first + row * (columns + 1) + column;
+        for row in 0..rows {
+            for column in 0..columns {
+                let [a, b, c, d] = [index(row, column), index(row + 1, column), index(row + 1, column + 1), index(row, column + 1)];
+                self.add_triangle(a, c, b);
+                self.add_triangle(a, d, c);
+            }
+        }
+    }
+
+    fn build(self, color: Color) -> Mesh {
+        let mut mesh = Mesh::new(self.vertices, self.groups).expect("triangles only use added vertices");
+        mesh.set_normals(self.normals).expect("one normal per vertex");
+        mesh.set_uvs(self.uvs).expect("one uv per vertex");
+        mesh.set_color(color);
+        return mesh;
+    }
+}
+
+// Flat disc at height y closing a shape of the given radius, facing up or down
+fn add_cap(builder: &mut Builder, radius: f32, y: f32, segments: usize, facing_up: bool) {
+    let normal = Vec3::new(0.0, if facing_up { 1.0 } else { -1.0 }, 0.0);
+    let center = builder.add_vertex(Point3::new(0.0, y, 0.0), normal, [0.5, 0.5]);
+    let first = builder.vertices.len();
+    for segment in 0..=segments {
+        let direction = around_y(segment as f32 / segments as f32);
+        let uv = [0.5 + direction.x / 2.0, 0.5 + direction.z / 2.0];
+        builder.add_vertex(Point3::new(0.0, y, 0.0) + direction * radius, normal, uv);
+    }
+    for segment in 0..segments {
+        let (current, next) = (first + segment, first + segment + 1);
+        if facing_up {
+            builder.add_triangle(center, next, current);
+        } else {
+            builder.add_triangle(center, current, next);
+        }
+    }
+}
+
+// Rings of constant latitude, u runs around the y axis and v from the top pole to the bottom one
+pub fn uv_sphere(radius: f32, segments: usize, rings: usize, color: Color) -> Mesh {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    let mut builder = Builder::new();
+
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let (sin, cos) = (v * PI).sin_cos();
+        for segment in 0..=segments {
+            // The poles are a point, their copies sit in the middle of each segment's UVs
+            let u = if ring == 0 || ring == rings { (segment as f32 + 0.5) / segments as f32 } else { segment as f32 / segments as f32 };
+            let normal = around_y(u) * sin + Vec3::new(0.0, cos, 0.0);
+            builder.add_vertex(Point3::ORIGIN + normal * radius, normal, [u, v]);
+        }
+    }
+
+    let index = |ring: usize, segment: usize| ring * (segments + 1) + segment;
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let [a, b, c, d] = [index(ring, segment), index(ring + 1, segment), index(ring + 1, segment + 1), index(ring, segment + 1)];
+            // Next to a pole the quad is a single triangle, with the pole's copy for this segment
+            if ring == 0 {
+                builder.add_triangle(a, c, b);
+            } else if ring == rings - 1 {
+                builder.add_triangle(a, d, b);
+            } else {
+                builder.add_triangle(a, c, b);
+                builder.add_triangle(a, d, c);
+            }
+        }
+    }
+
+    return builder.build(color);
+}
+
+// Icosahedron with every face split into four for each subdivision, the new vertices pushed out to the
+// sphere. Triangles are close to the same size everywhere, unlike the crowded poles of a UV sphere
+pub fn icosphere(radius: f32, subdivisions: u32, color: Color) -> Mesh {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut directions: Vec<Vec3> = [
+        [-1.0, t, 0.0], [1.0, t, 0.0], [-1.0, -t, 0.0], [1.0, -t, 0.0],
+        [0.0, -1.0, t], [0.0, 1.0, t], [0.0, -1.0, -t], [0.0, 1.0, -t],
+        [t, 0.0, -1.0], [t, 0.0, 1.0], [-t, 0.0, -1.0], [-t, 0.0, 1.0],
+    ]
+        .iter()
+        .map(|&direction| Vec3::from(direction).normalize())
+        .collect();
+    let mut faces: Vec<[usize; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Neighbouring faces share the vertex in the middle of their common edge
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |p: usize, q: usize| {
+            *midpoints.entry((p.min(q), p.max(q))).or_insert_with(|| {
+                directions.push((directions[p] + directions[q]).normalize());
+                directions.len() - 1
+            })
+        };
+        faces = faces
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let [ab, bc, ca] = [midpoint(a, b), midpoint(b, c), midpoint(c, a)];
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // Triangles crossing the seam wrap past u = 1 instead of spanning the texture, so their corners there,
+    // and the poles, get a copy of the vertex with their own UV
+    let mut builder = Builder::new();
+    let mut copies: HashMap<(usize, [u32; 2]), usize> = HashMap::new();
+    for group in faces {
+        let uvs = spherical_uvs(group.map(|idx| directions[idx]));
+        let corners = [0, 1, 2].map(|corner| {
+            let (idx, uv) = (group[corner], uvs[corner]);
+            *copies.entry((idx, uv.map(f32::to_bits))).or_insert_with(|| {
+                builder.add_vertex(Point3::ORIGIN + directions[idx] * radius, directions[idx], uv)
+            })
+        });
+        builder.add_triangle(corners[0], corners[1], corners[2]);
+    }
+    return builder.build(color);
+}
+
+fn spherical_uvs(directions: [Vec3; 3]) -> [[f32; 2]; 3] {
+    let is_pole = |direction: Vec3| direction.x.abs() < 1e-6 && direction.z.abs() < 1e-6;
+    let mut uvs = directions.map(|direction| {
+        let u = 0.5 + direction.z.atan2(direction.x) / TAU;
+        let v = 0.5 - direction.y.clamp(-1.0, 1.0).asin() / PI;
+        [u, v]
+    });
+
+    let us: Vec<f32> = (0..3).filter(|&idx| !is_pole(directions[idx])).map(|idx| uvs[idx][0]).collect();
+    let wraps = us.iter().fold(0.0f32, |max, &u| max.max(u)) - us.iter().fold(1.0f32, |min, &u| min.min(u)) > 0.5;
+    for uv in uvs.iter_mut() {
+        if wraps && uv[0] < 0.5 { uv[0] += 1.0; }
+    }
+    // A pole has no longitude, it takes the middle of the other two corners
+    for idx in 0..3 {
+        if is_pole(directions[idx]) {
+            let others: Vec<f32> = (0..3).filter(|&other| !is_pole(directions[other])).map(|other| uvs[other][0]).collect();
+            uvs[idx][0] = others.iter().sum::<f32>() / others.len().max(1) as f32;
+        }
+    }
+    return uvs;
+}
+
+// Capped cylinder along the y axis from -height / 2 to height / 2
+pub fn cylinder(radius: f32, height: f32, segments: usize, color: Color) -> Mesh {
+    let segments = segments.max(3);
+    let mut builder = Builder::new();
+    let half_height = height / 2.0;
+
+    let first = builder.vertices.len();
+    for (y, v) in [(half_height, 0.0), (-half_height, 1.0)] {
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let normal = around_y(u);
+            builder.add_vertex(Point3::new(0.0, y, 0.0) + normal * radius, normal, [u, v]);
+        }
+    }
+    builder.add_quads(first, segments, 1);
+
+    add_cap(&mut builder, radius, half_height, segments, true);
+    add_cap(&mut builder, radius, -half_height, segments, false);
+    return builder.build(color);
+}
+
+// Cone along the y axis with its base at -height / 2 and its tip at height / 2
+pub fn cone(radius: f32, height: f32, segments: usize, color: Color) -> Mesh {
+    let segments = segments.max(3);
+    let mut builder = Builder::new();
+    let half_height = height / 2.0;
+    // The side leans in, so its normals tilt up by the slope
+    let slant = (radius * radius + height * height).sqrt();
+    let normal_at = |u: f32| around_y(u) * (height / slant) + Vec3::new(0.0, radius / slant, 0.0);
+
+    // The tip gets a copy per segment with the normal of the segment's middle
+    let first = builder.vertices.len();
+    for segment in 0..=segments {
+        let u = (segment as f32 + 0.5) / segments as f32;
+        builder.add_vertex(Point3::new(0.0, half_height, 0.0), normal_at(u), [u, 0.0]);
+    }
+    for segment in 0..=segments {
+        let u = segment as f32 / segments as f32;
+        builder.add_vertex(Point3::new(0.0, -half_height, 0.0) + around_y(u) * radius, normal_at(u), [u, 1.0]);
+    }
+    for segment in 0..segments {
+        let tip = first + segment;
+        let base = first + segments + 1 + segment;
+        builder.add_triangle(tip, base + 1, base);
+    }
+
+    add_cap(&mut builder, radius, -half_height, segments, false);
+    return builder.build(color);
+}
+
+// Ring around the y axis, the tube's center circle has the major radius and the tube the minor one.
+// Segments go around the ring and sides around the tube
+pub fn torus(major_radius: f32, minor_radius: f32, segments: usize, sides: usize, color: Color) -> Mesh {
+    let (segments, sides) = (segments.max(3), sides.max(3));
+    let mut builder = Builder::new();
+
+    // Rows go around the tube from the top over the outside, so they run downwards like the other grids
+    for side in 0..=sides {
+        let v = side as f32 / sides as f32;
+        let (sin, cos) = (PI / 2.0 - v * TAU).sin_cos();
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let outwards = around_y(u);
+            let normal = outwards * cos + Vec3::new(0.0, sin, 0.0);
+            builder.add_vertex(Point3::ORIGIN + outwards * major_radius + normal * minor_radius, normal, [u, v]);
+        }
+    }
+    builder.add_quads(0, segments, sides);
+
+    return builder.build(color);
+}
+
+// Flat grid on the xz plane facing up, split into subdivisions by subdivisions squares
+pub fn plane(width: f32, depth: f32, subdivisions: usize, color: Color) -> Mesh {
+    let subdivisions = subdivisions.max(1);
+    let mut builder = Builder::new();
+    let normal = Vec3::new(0.0, 1.0, 0.0);
+
+    for row in 0..=subdivisions {
+        let v = row as f32 / subdivisions as f32;
+        for column in 0..=subdivisions {
+            let u = column as f32 / subdivisions as f32;
+            builder.add_vertex(Point3::new((u - 0.5) * width, 0.0, (0.5 - v) * depth), normal, [u, v]);
+        }
+    }
+    builder.add_quads(0, subdivisions, subdivisions);
+
+    return builder.build(color);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use crate::geometry::Facet;
+    use crate::math::Mat4;
+
+    fn facets(mesh: &Mesh) -> Vec<Facet> {
+        return mesh.get_facets(Mat4::IDENTITY);
+    }
+
+    fn area(mesh: &Mesh) -> f32 {
+        return facets(mesh).iter().map(|facet| {
+            let [a, b, c] = facet.vertices;
+            (b - a).cross(c - a).length() / 2.0
+        }).sum();
+    }
+
+    // Divergence theorem: comes out as the enclosed volume only for a closed surface wound outwards
+    fn volume(mesh: &Mesh) -> f32 {
+        return facets(mesh).iter().map(|facet| {
+            let [a, b, c] = facet.vertices.map(|vertex| vertex.to_vec3());
+            a.dot(b.cross(c)) / 6.0
+        }).sum();
+    }
+
+    // Corner normals are unit length and on the side the triangle faces
+    fn normals_follow_winding(mesh: &Mesh) -> bool {
+        return facets(mesh).iter().all(|facet| {
+            let normals = facet.normals.unwrap();
+            normals.iter().all(|normal| (normal.length() - 1.0).abs() < 1e-4 && normal.dot(facet.get_normal()) > 0.0)
+        });
+    }
+
+    fn close(value: f32, expected: f32, tolerance: f32) -> bool {
+        return (value - expected).abs() <= tolerance * expected.abs();
+    }
+
+    fn size() -> std::ops::Range<f32> {
+        0.5f32..5.0
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn spheres_match_the_analytic_sphere(radius in size()) {
+            let expected_area = 4.0 * PI * radius * radius;
+            let expected_volume = 4.0 / 3.0 * PI * radius.powi(3);
+            for mesh in [uv_sphere(radius, 64, 32, Color::WHITE), icosphere(radius, 4, Color::WHITE)] {
+                prop_assert!(close(area(&mesh), expected_area, 0.01), "{} against {}", area(&mesh), expected_area);
+                prop_assert!(close(volume(&mesh), expected_volume, 0.02), "{} against {}", volume(&mesh), expected_volume);
+                prop_assert!(normals_follow_winding(&mesh));
+                for facet in facets(&mesh) {
+                    for (vertex, normal) in facet.vertices.iter().zip(facet.normals.unwrap()) {
+                        prop_assert!((vertex.to_vec3() - normal * radius).length() < 1e-4 * radius);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn cylinders_and_cones_match_their_analytic_shape(radius in size(), height in size()) {
+            let mesh = cylinder(radius, height, 64, Color::WHITE);
+            prop_assert!(close(area(&mesh), 2.0 * PI * radius * (radius + height), 0.01));
+            prop_assert!(close(volume(&mesh), PI * radius * radius * height, 0.01));
+            prop_assert!(normals_follow_winding(&mesh));
+
+            let mesh = cone(radius, height, 64, Color::WHITE);
+            let slant = (radius * radius + height * height).sqrt();
+            prop_assert!(close(area(&mesh), PI * radius * (radius + slant), 0.01));
+            prop_assert!(close(volume(&mesh), PI * radius * radius * height / 3.0, 0.01));
+            prop_assert!(normals_follow_winding(&mesh));
+        }
+
+        #[test]
+        fn tori_match_the_analytic_torus(major_radius in 1.0f32..5.0, share in 0.1f32..0.9) {
+            let minor_radius = major_radius * share;
+            let mesh = torus(major_radius, minor_radius, 64, 32, Color::WHITE);
+            prop_assert!(close(area(&mesh), 4.0 * PI * PI * major_radius * minor_radius, 0.01));
+            prop_assert!(close(volume(&mesh), 2.0 * PI * PI * major_radius * minor_radius * minor_radius, 0.02));
+            prop_assert!(normals_follow_winding(&mesh));
+        }
+    }
+
+    #[test]
+    fn planes_face_up_and_cover_their_size() {
+        let mesh = plane(4.0, 2.0, 3, Color::WHITE);
+        assert_eq!(facets(&mesh).len(), 18);
+        assert!(close(area(&mesh), 8.0, 1e-5));
+        assert!(normals_follow_winding(&mesh));
+        assert!(facets(&mesh).iter().all(|facet| facet.get_normal().y > 0.999));
+    }
+
+    // UVs cover [0, 1] on both axes, apart from icosphere triangles wrapping over the seam
+    #[test]
+    fn uvs_cover_the_texture_once() {
+        let in_range = |mesh: &Mesh, max_u: f32| facets(mesh).iter().flat_map(|facet| facet.uvs).all(|[u, v]| {
+            (-1e-6 ..= max_u + 1e-6).contains(&u) && (-1e-6 ..= 1.0 + 1e-6).contains(&v)
+        });
+        for mesh in [uv_sphere(1.0, 8, 4, Color::WHITE), cylinder(1.0, 1.0, 8, Color::WHITE), cone(1.0, 1.0, 8, Color::WHITE), torus(2.0, 1.0, 8, 4, Color::WHITE), plane(1.0, 1.0, 2, Color::WHITE)] {
+            assert!(in_range(&mesh, 1.0));
+        }
+        let mesh = icosphere(1.0, 2, Color::WHITE);
+        assert!(in_range(&mesh, 1.5));
+        // No triangle stretches across the whole texture
+        assert!(facets(&mesh).iter().all(|facet| {
+            let us = facet.uvs.map(|uv| uv[0]);
+            us.iter().fold(0.0f32, |max, &u| max.max(u)) - us.iter().fold(2.0f32, |min, &u| min.min(u)) < 0.5
+        }));
+    }
+
+    // The UV sphere lines its texture up with the raytracer's analytic sphere
+    #[test]
+    fn uv_sphere_uvs_match_the_analytic_sphere() {
+        let sphere = crate::geometry::Sphere { radius: 2.0, center: Point3::ORIGIN, color: Color::WHITE, specular: 0.0, reflective: 0.0, texture: None };
+        for facet in facets(&uv_sphere(2.0, 16, 8, Color::WHITE)) {
+            for (vertex, uv) in facet.vertices.iter().zip(facet.uvs) {
+                // Seam and pole copies have their own u
+                if vertex.x.abs() < 1e-3 && vertex.z.abs() < 1e-3 { continue; }
+                let expected = sphere.get_uv(*vertex);
+                let du = (expected[0] - uv[0]).abs();
+                assert!(du.min((du - 1.0).abs()) < 1e-4 && (expected[1] - uv[1]).abs() < 1e-4);
+            }
+        }
+    }
+}
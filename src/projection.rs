@@ -1,29 +1,112 @@
-use crate::utilities::{Axis, interpolate, multiply_color, vector_multiplication, vector_addition, rotate_vector, to_translation_mat4, to_scale_mat4, to_rotation_mat4, mat4_default, multiply_mat4_mat4, to_inverse_translation_mat4, to_inverse_rotation_mat4};
+use crate::math::{Vec3, Point3, Mat4};
+use crate::utilities::to_inverse_translation_mat4;
+use crate::rotation::{Rotation, Quaternion};
 
+// Orthonormal right/up/forward frame of a camera, forward maps to +Z in camera space like both renderers expect
+#[derive(Clone, Copy, Debug)]
+pub struct CameraBasis {
+    pub right: Vec3,
+    pub up: Vec3,
+    pub forward: Vec3,
+}
+
+impl CameraBasis {
+    pub fn new(forward: Vec3, up: Vec3) -> Self {
+        let forward = if forward.length() == 0.0 { Vec3::new(0.0, 0.0, 1.0) } else { forward.normalize() };
+
+        // An up vector parallel to the view direction has no unique right vector, borrow another world axis
+        let up = if up.cross(forward).length() < 1e-6 {
+            if forward.z.abs() < 0.9 { Vec3::new(0.0, 0.0, 1.0) } else { Vec3::new(0.0, 1.0, 0.0) }
+        } else {
+            up
+        };
+
+        let right = up.cross(forward).normalize();
+        let up = forward.cross(right);
+        return Self { right, up, forward };
+    }
+
+    // Camera-to-world rotation, the basis vectors are the columns
+    pub fn to_mat4(self) -> Mat4 {
+        let (r, u, f) = (self.right, self.up, self.forward);
+        Mat4([
+            [r.x, u.x, f.x, 0.0],
+            [r.y, u.y, f.y, 0.0],
+            [r.z, u.z, f.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // World-to-camera matrix for a camera at `eye`, the transpose of the rotation followed by the inverse translation
+    pub fn to_view_mat4(self, eye: Point3) -> Mat4 {
+        let (r, u, f) = (self.right, self.up, self.forward);
+        let eye = eye.to_vec3();
+        Mat4([
+            [r.x, r.y, r.z, -r.dot(eye)],
+            [u.x, u.y, u.z, -u.dot(eye)],
+            [f.x, f.y, f.z, -f.dot(eye)],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct Camera {
-    position: [f32; 3],
-    rotation: Option<(Axis, f32)>,
+    position: Point3,
+    rotation: Option<Rotation>,
 }
 
 impl Camera {
-    pub fn new(position: [f32; 3], rotation: Option<(Axis, f32)>) -> Self {
+    pub fn new(position: Point3, rotation: Option<Rotation>) -> Self {
         Self { position, rotation }
     }
-    pub fn get_projection_mat4(&mut self) -> [[f32; 4]; 4] {
-        let origin = mat4_default();
-        let translation_mat4 = to_inverse_translation_mat4(self.position);
+
+    // Camera at `eye` aimed at `target`, `up` only needs to roughly point up
+    pub fn look_at(eye: Point3, target: Point3, up: Vec3) -> Self {
+        let basis = CameraBasis::new(target - eye, up);
+        let orientation = Quaternion::from_mat4(basis.to_mat4());
+        return Self::new(eye, Some(Rotation::Quaternion(orientation)));
+    }
+
+    pub fn get_position(&self) -> Point3 {
+        return self.position;
+    }
+
+    pub fn get_basis(&self) -> CameraBasis {
+        let rotate = |axis: Vec3| match &self.rotation {
+            Some(rotation) => rotation.rotate_vector(axis),
+            None => axis,
+        };
+        return CameraBasis {
+            right: rotate(Vec3::new(1.0, 0.0, 0.0)),
+            up: rotate(Vec3::new(0.0, 1.0, 0.0)),
+            forward: rotate(Vec3::new(0.0, 0.0, 1.0)),
+        };
+    }
+
+    // Camera-to-world transform, the inverse of the view matrix
+    pub fn get_world_mat4(&self) -> Mat4 {
+        let rotation_mat4 = match &self.rotation {
+            Some(rotation) => rotation.to_mat4(),
+            None => Mat4::IDENTITY,
+        };
+        return Mat4::translation(self.position.to_vec3()) * rotation_mat4;
+    }
+    pub fn get_projection_mat4(&mut self) -> Mat4 {
+        let origin = Mat4::IDENTITY;
+        let translation_mat4 = Mat4::from(to_inverse_translation_mat4(self.position.to_array()));
         let rotation_mat4 = self.handle_inverse_rotation_mat4();
 
-        let rotated_projection = multiply_mat4_mat4(origin, rotation_mat4);
-        let translated_projection = multiply_mat4_mat4(rotated_projection, translation_mat4);
+        let rotated_projection = origin * rotation_mat4;
+        let translated_projection = rotated_projection * translation_mat4;
 
         return translated_projection;
     }
 
-    fn handle_inverse_rotation_mat4(&mut self) -> [[f32; 4]; 4] {
+    fn handle_inverse_rotation_mat4(&mut self) -> Mat4 {
         return match &self.rotation {
-            Some((axis, angle)) => { to_inverse_rotation_mat4(*axis, angle.to_radians()) },
-            None => mat4_default()
+            Some(rotation) => { rotation.to_inverse_mat4() },
+            None => Mat4::IDENTITY
         }
     }
 }
@@ -40,40 +123,150 @@ impl PerspectiveProjection {
         Self { field_of_view: field_of_view.to_radians(), aspect_ratio, near_clipping_plane, far_clipping_plane }
     }
 
-    pub fn get_projection_mat4(&self) -> [[f32; 4]; 4] {
+    pub fn get_projection_mat4(&self) -> Mat4 {
         let tangent_mid_fov = (self.field_of_view / 2.0).tan();
         let perspective_scale = 1.0 / tangent_mid_fov;
         let depth_range_scale = 1.0 / (self.near_clipping_plane - self.far_clipping_plane);
         let ndc_depth_scale = (self.far_clipping_plane + self.near_clipping_plane) * depth_range_scale;
         let perspective_divide_factor = 2.0 * self.far_clipping_plane * self.near_clipping_plane * depth_range_scale;
 
-        [
+        Mat4([
             [perspective_scale / self.near_clipping_plane, 0.0, 0.0, 0.0],
             [0.0, perspective_scale, 0.0, 0.0],
             [0.0, 0.0, ndc_depth_scale, perspective_divide_factor],
             [0.0, 0.0, -1.0, 0.0],
-        ]
+        ])
+    }
+
+    pub fn get_clipping_planes(&self) -> (f32, f32) {
+        (self.near_clipping_plane, self.far_clipping_plane)
+    }
+
+    // The field of view spans the canvas, the vertex must be in front of the camera
+    pub fn window_to_canvas(&self, vertex: Point3, canvas: [i32; 2]) -> [f32; 2] {
+        let perspective_scale = 1.0 / (self.field_of_view / 2.0).tan();
+        let x_pos = vertex.x / vertex.z * perspective_scale / self.aspect_ratio * canvas[0] as f32 / 2.0;
+        let y_pos = vertex.y / vertex.z * perspective_scale * canvas[1] as f32 / 2.0;
+        [x_pos, y_pos]
     }
 }
 
-pub struct Viewport {
-    width: f32,
-    height: f32,
+pub struct OrthographicProjection {
+    left: f32,
+    right: f32,
+    bottom: f32,
+    top: f32,
+    near_clipping_plane: f32,
+    far_clipping_plane: f32,
 }
 
-impl Viewport {
-    pub fn new(width: f32, height: f32) -> Self {
-        Self { width, height }
+impl OrthographicProjection {
+    pub fn new(left: f32, right: f32, bottom: f32, top: f32, near_clipping_plane: f32, far_clipping_plane: f32) -> Self {
+        Self { left, right, bottom, top, near_clipping_plane, far_clipping_plane }
     }
-    pub fn to_canvas_mat4(&self) -> [[f32; 4]; 4] {
-        let half_width = self.width / 2.0;
-        let half_height = self.height / 2.0;
 
-        [
-            [half_width, 0.0, 0.0, half_width],
-            [0.0, half_height, 0.0, half_height],
-            [0.0, 0.0, 1.0, 0.0],
+    pub fn get_projection_mat4(&self) -> Mat4 {
+        let width = self.right - self.left;
+        let height = self.top - self.bottom;
+        let depth = self.far_clipping_plane - self.near_clipping_plane;
+
+        Mat4([
+            [2.0 / width, 0.0, 0.0, -(self.right + self.left) / width],
+            [0.0, 2.0 / height, 0.0, -(self.top + self.bottom) / height],
+            [0.0, 0.0, -2.0 / depth, -(self.far_clipping_plane + self.near_clipping_plane) / depth],
             [0.0, 0.0, 0.0, 1.0],
-        ]
+        ])
+    }
+
+    pub fn get_clipping_planes(&self) -> (f32, f32) {
+        (self.near_clipping_plane, self.far_clipping_plane)
+    }
+
+    pub fn window_to_canvas(&self, vertex: Point3, canvas: [i32; 2]) -> [f32; 2] {
+        let x_center = (self.left + self.right) / 2.0;
+        let y_center = (self.bottom + self.top) / 2.0;
+        let x_pos = (vertex.x - x_center) * canvas[0] as f32 / (self.right - self.left);
+        let y_pos = (vertex.y - y_center) * canvas[1] as f32 / (self.top - self.bottom);
+        [x_pos, y_pos]
+    }
+
+    pub fn canvas_to_window(&self, x: i32, y: i32, canvas: [i32; 2]) -> Point3 {
+        let x_center = (self.left + self.right) / 2.0;
+        let y_center = (self.bottom + self.top) / 2.0;
+        let x_pos = x_center + x as f32 * (self.right - self.left) / canvas[0] as f32;
+        let y_pos = y_center + y as f32 * (self.top - self.bottom) / canvas[1] as f32;
+        Point3::new(x_pos, y_pos, 0.0)
+    }
+}
+
+pub enum Projection {
+    Perspective(PerspectiveProjection),
+    Orthographic(OrthographicProjection),
+}
+
+// Which kind of projection a view uses, without its parameters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProjectionMode {
+    Perspective,
+    Orthographic,
+}
+
+impl Projection {
+    pub fn get_mode(&self) -> ProjectionMode {
+        match self {
+            Projection::Perspective(_) => ProjectionMode::Perspective,
+            Projection::Orthographic(_) => ProjectionMode::Orthographic,
+        }
+    }
+
+    pub fn get_projection_mat4(&self) -> Mat4 {
+        match self {
+            Projection::Perspective(perspective) => perspective.get_projection_mat4(),
+            Projection::Orthographic(orthographic) => orthographic.get_projection_mat4(),
+        }
+    }
+
+    pub fn get_clipping_planes(&self) -> (f32, f32) {
+        match self {
+            Projection::Perspective(perspective) => perspective.get_clipping_planes(),
+            Projection::Orthographic(orthographic) => orthographic.get_clipping_planes(),
+        }
+    }
+
+    pub fn window_to_canvas(&self, vertex: Point3, canvas: [i32; 2]) -> [f32; 2] {
+        match self {
+            Projection::Perspective(perspective) => perspective.window_to_canvas(vertex, canvas),
+            Projection::Orthographic(orthographic) => orthographic.window_to_canvas(vertex, canvas),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn look_at_puts_the_target_straight_ahead() {
+        let eye = Point3::new(-4.0, 3.0, 2.0);
+        let target = Point3::new(0.0, 1.0, 7.25);
+        let mut camera = Camera::look_at(eye, target, Vec3::new(0.0, 1.0, 0.0));
+
+        let basis = camera.get_basis();
+        assert!((basis.forward - (target - eye).normalize()).length() < 1e-5);
+        assert!(basis.right.dot(basis.up).abs() < 1e-5 && basis.right.y.abs() < 1e-5);
+
+        // The view matrix sends the target onto the +Z axis and agrees with the basis built directly
+        let view = camera.get_projection_mat4();
+        let in_view = view.transform_point(target);
+        assert!(in_view.x.abs() < 1e-4 && in_view.y.abs() < 1e-4 && in_view.z > 0.0);
+        let expected = basis.to_view_mat4(eye);
+        assert!(view.0.iter().flatten().zip(expected.0.iter().flatten()).all(|(a, b)| (a - b).abs() < 1e-4));
+    }
+
+    #[test]
+    fn basis_survives_an_up_vector_along_the_view() {
+        let basis = CameraBasis::new(Vec3::new(0.0, -2.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!((basis.right.length() - 1.0).abs() < 1e-5 && (basis.up.length() - 1.0).abs() < 1e-5);
+        assert!(basis.right.dot(basis.forward).abs() < 1e-5 && basis.up.dot(basis.forward).abs() < 1e-5);
     }
 }
@@ -1,265 +1,1648 @@
-use crate::geometry::{ Vertex };
-use crate::projection::{Camera, PerspectiveProjection, Viewport};
-use crate::utilities::{Axis, multiply_mat4_vec, interpolate, multiply_color, vector_multiplication, vector_addition, rotate_vector, to_translation_mat4, to_scale_mat4, to_rotation_mat4, mat4_default, multiply_mat4_mat4, to_inverse_translation_mat4, to_inverse_rotation_mat4};
-
-const RED: [f32; 3] = [255.0, 0.0, 0.0];
-const GREEN: [f32; 3] = [0.0, 255.0, 0.0];
-const BLUE: [f32; 3] = [0.0, 0.0, 255.0];
-const PURPLE: [f32; 3] = [255.0, 0.0, 255.0];
-const YELLOW: [f32; 3] = [255.0, 255.0, 0.0];
-const CYAN: [f32; 3] = [0.0, 255.0, 255.0];
-
-pub struct Triangle {
-    group: [usize; 3],
-    color: [f32; 3],
+use std::rc::Rc;
+use crate::geometry::{ Vertex, Light, LightMode, Sphere };
+use crate::math::{Vec3, Point3, Mat4, Color, Rgba, BlendMode};
+use crate::texture::{Texture, Filter, WrapMode};
+use crate::scene::{Node, Shape};
+use crate::frame::{Frame, BACKGROUND};
+use crate::rotation::{Rotation, EulerOrder};
+use crate::projection::{Camera, PerspectiveProjection, Projection};
+use crate::clipping::{canvas_bounds, clip_polygon};
+use crate::drawing::{self, Canvas};
+use crate::primitives;
+use crate::mesh::Mesh;
+use crate::shadow::{ShadowMap, ShadowSettings};
+use crate::gbuffer::{GBuffer, Sample};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderMode {
+    Wireframe,
+    Filled,
+    Shaded,
+}
+
+// How the pixels inside a triangle are found: row spans between the left and right edges, or edge
+// functions tested over the bounding box in tiles with barycentric weights for the attributes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Traversal {
+    Scanline,
+    EdgeFunction,
+}
+
+// When the shaded mode lights the pixels: as each triangle is filled, or once per pixel after every
+// opaque triangle has left its surface in the G-buffer, so overdrawn pixels are never lit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderPath {
+    Forward,
+    Deferred,
+}
+
+// How wireframe edges are drawn: one pixel per step, or Xiaolin Wu's coverage blended over the canvas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineMode {
+    Aliased,
+    AntiAliased,
+}
+
+// A triangle corner with everything the fill needs, corners made by near plane clipping are
+// interpolated from the mesh's own in camera space
+#[derive(Clone, Copy)]
+struct Corner {
+    point: [f32; 2],
+    view: Point3,
+    world: Point3,
+    normal: Option<Vec3>,
+    uv: [f32; 2],
+    color: Rgba,
+}
+
+impl Corner {
+    fn lerp(self, other: Corner, t: f32) -> Self {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        // The canvas point is not linear in camera space, it is projected again from `view`
+        Self {
+            point: self.point,
+            view: self.view + (other.view - self.view) * t,
+            world: self.world + (other.world - self.world) * t,
+            normal: self.normal.zip(other.normal).map(|(a, b)| (a + (b - a) * t).normalize()),
+            uv: [mix(self.uv[0], other.uv[0]), mix(self.uv[1], other.uv[1])],
+            color: Rgba::new(mix(self.color.r, other.color.r), mix(self.color.g, other.color.g), mix(self.color.b, other.color.b), mix(self.color.a, other.color.a)),
+        }
+    }
+}
+
+// World-space corners of a shaded triangle, every pixel is lit from the surface between them
+#[derive(Clone, Copy)]
+struct Surface {
+    positions: [Point3; 3],
+    normals: [Vec3; 3],
+}
+
+// Planes of the weighted world position and normal across the screen-space triangle, which are linear
+// there like the weighted UVs. Each plane is its value at `origin` and its gradient
+struct SurfacePlanes {
+    origin: [f32; 2],
+    planes: [(f32, [f32; 2]); 6],
+}
+
+impl SurfacePlanes {
+    fn new(points: [[f32; 2]; 3], weights: [f32; 3], surface: Surface) -> Self {
+        let planes = std::array::from_fn(|idx| {
+            let values = [0, 1, 2].map(|corner| {
+                let corner_value = if idx < 3 { surface.positions[corner].to_array() } else { surface.normals[corner].to_array() };
+                corner_value[idx % 3] * weights[corner]
+            });
+            (values[0], Rasterizer::attribute_gradient(points, values))
+        });
+        return Self { origin: points[0], planes };
+    }
+
+    // Position and unit normal at a pixel, given the interpolation weight there
+    fn at(&self, point: [f32; 2], weight: f32) -> (Point3, Vec3) {
+        let values = self.planes.map(|(value, gradient)| Rasterizer::attribute_at(self.origin, value, gradient, point) / weight);
+        return (Point3::new(values[0], values[1], values[2]), Vec3::new(values[3], values[4], values[5]).normalize());
+    }
+}
+
+// What the pixels of a triangle share besides the interpolated corners: the texture multiplied by the
+// colors, the blend mode mixing them with what is drawn, the corners of a shaded triangle and its
+// specular exponent
+#[derive(Clone, Copy, Default)]
+struct Shading<'a> {
+    texture: Option<&'a Texture>,
+    blend_mode: Option<BlendMode>,
+    surface: Option<Surface>,
+    specular: f32,
+}
+
+// A translucent triangle held back until every opaque one is in the depth buffer
+struct TranslucentTriangle {
+    points: [[f32; 2]; 3],
+    depths: [f32; 3],
+    uvs: [[f32; 2]; 3],
+    texture: Option<Rc<Texture>>,
+    colors: [Rgba; 3],
+    blend_mode: BlendMode,
+    surface: Option<Surface>,
+    specular: f32,
+}
+impl TranslucentTriangle {
+    // Distance used to sort the triangles, the mean view depth of the corners
+    fn get_depth(&self) -> f32 {
+        return self.depths.iter().sum::<f32>() / 3.0;
+    }
 }
 
 pub struct Box {
-    scale: [f32; 3],
-    rotation: Option<(Axis, f32)>,
-    position: [f32; 3],
+    scale: Vec3,
+    rotation: Option<Rotation>,
+    position: Point3,
 }
 impl Default for Box {
     fn default() -> Self {
         Self {
-            scale: [1.0, 1.0, 1.0],
+            scale: Vec3::new(1.0, 1.0, 1.0),
             rotation: None,
-            position: [0.0, 0.0, 0.0],
+            position: Point3::ORIGIN,
         }
     }
 }
 impl Box {
-    pub fn new(scale: [f32; 3], position: [f32; 3], rotation: Option<(Axis, f32)>) -> Self {
+    pub fn new(scale: Vec3, position: Point3, rotation: Option<Rotation>) -> Self {
         Self { scale, position, rotation }
     }
-    pub const VERTICES: [[f32; 3]; 8] = [
-        [ 1.0,  1.0,  1.0],
-        [-1.0,  1.0,  1.0],
-        [-1.0, -1.0,  1.0],
-        [ 1.0, -1.0,  1.0],
-        [ 1.0,  1.0, -1.0],
-        [-1.0,  1.0, -1.0],
-        [-1.0, -1.0, -1.0],
-        [ 1.0, -1.0, -1.0],
+    pub const VERTICES: [Point3; 8] = [
+        Point3::new( 1.0,  1.0,  1.0),
+        Point3::new(-1.0,  1.0,  1.0),
+        Point3::new(-1.0, -1.0,  1.0),
+        Point3::new( 1.0, -1.0,  1.0),
+        Point3::new( 1.0,  1.0, -1.0),
+        Point3::new(-1.0,  1.0, -1.0),
+        Point3::new(-1.0, -1.0, -1.0),
+        Point3::new( 1.0, -1.0, -1.0),
     ];
-    pub const TRIANGLES: [Triangle; 12] = [
-        Triangle { group: [0, 1, 2], color: RED },
-        Triangle { group: [0, 2, 3], color: RED },
-        Triangle { group: [4, 0, 3], color: GREEN },
-        Triangle { group: [4, 3, 7], color: GREEN },
-        Triangle { group: [5, 4, 7], color: BLUE },
-        Triangle { group: [5, 7, 6], color: BLUE },
-        Triangle { group: [1, 5, 6], color: YELLOW },
-        Triangle { group: [1, 6, 2], color: YELLOW },
-        Triangle { group: [4, 5, 1], color: PURPLE },
-        Triangle { group: [4, 1, 0], color: PURPLE },
-        Triangle { group: [2, 6, 7], color: CYAN },
-        Triangle { group: [2, 7, 3], color: CYAN },
+    // Corners of each face counter-clockwise from outside, split into two triangles along the first corner
+    pub const FACES: [([usize; 4], Color); 6] = [
+        ([0, 1, 2, 3], Color::RED),
+        ([4, 0, 3, 7], Color::GREEN),
+        ([5, 4, 7, 6], Color::BLUE),
+        ([1, 5, 6, 2], Color::YELLOW),
+        ([4, 5, 1, 0], Color::PURPLE),
+        ([2, 6, 7, 3], Color::CYAN),
     ];
+    const FACE_UVS: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
 
-    pub fn get_geometry(&mut self) -> (Vec<[f32; 3]>, Vec<Triangle>) {
-        let scale = self.scale;
-        let position = self.position;
-
-        let transformed = Self::VERTICES
-            .iter()
-            .map(|&f| vector_multiplication(f, scale))
-            .map(|f| self.handle_rotation(f))
-            .map(|f| vector_addition(f, position))
-            .collect();
-
-        return (transformed, Vec::from(Self::TRIANGLES));
-    }
-
-    fn handle_rotation(&mut self, vertex: [f32; 3]) -> [f32; 3] {
-        return match &self.rotation {
-            Some(rotation) => { rotate_vector(vertex, rotation) },
-            None => vertex
+    // Unit cube in local space, shared by every box through its model matrix. Every face has its own
+    // four vertices, so each keeps its color and covers the whole texture
+    pub fn get_mesh() -> Mesh {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        let mut uvs = vec![];
+        let mut colors = vec![];
+        for (corners, color) in Self::FACES {
+            let first = vertices.len();
+            vertices.extend(corners.map(|corner| Self::VERTICES[corner]));
+            uvs.extend(Self::FACE_UVS);
+            colors.extend([Rgba::opaque(color); 4]);
+            indices.push([first, first + 1, first + 2]);
+            indices.push([first, first + 2, first + 3]);
         }
+
+        let mut mesh = Mesh::new(vertices, indices).expect("box faces use the box's vertices");
+        mesh.set_uvs(uvs).expect("one uv per vertex");
+        mesh.set_colors(colors).expect("one color per vertex");
+        return mesh;
     }
 
-    pub fn get_model_mat4(&mut self) -> [[f32; 4]; 4] {
-        let origin = mat4_default();
-        let translation_mat4 = to_translation_mat4(self.position);
-        let scale_mat4 = to_scale_mat4(self.scale);
+    // Local-space box to world: scaled first, then rotated, then moved to its position
+    pub fn get_model_mat4(&self) -> Mat4 {
+        let origin = Mat4::IDENTITY;
+        let translation_mat4 = Mat4::translation(self.position.to_vec3());
+        let scale_mat4 = Mat4::scale(self.scale);
         let rotation_mat4 = self.handle_rotation_mat4();
 
-        let translated_projection = multiply_mat4_mat4(origin, translation_mat4);
-        let rotated_projection = multiply_mat4_mat4(translated_projection, rotation_mat4);
-        let scaled_projection =  multiply_mat4_mat4(rotated_projection, scale_mat4);
+        let translated_projection = origin * translation_mat4;
+        let rotated_projection = translated_projection * rotation_mat4;
+        let scaled_projection = rotated_projection * scale_mat4;
 
         return scaled_projection;
     }
 
-    fn handle_rotation_mat4(&mut self) -> [[f32; 4]; 4] {
+    fn handle_rotation_mat4(&self) -> Mat4 {
         return match &self.rotation {
-            Some((axis, angle)) => { to_rotation_mat4(*axis, angle.to_radians()) },
-            None => mat4_default()
+            Some(rotation) => { rotation.to_mat4() },
+            None => Mat4::IDENTITY
         }
     }
 }
 
 pub struct Rasterizer {
     state: Vec<Vertex>,
-    viewport: Viewport,
+    canvas: [i32; 2],
+    depth_buffer: Vec<f32>,
+    // Index in state of the last point drawn on each pixel, so coverage can blend with what is already there
+    pixel_owners: Vec<u32>,
     camera: Camera,
-    projection: PerspectiveProjection,
+    projection: Projection,
+    scene: Option<Node>,
+    lights: Vec<Light>,
+    mode: RenderMode,
+    traversal: Traversal,
+    line_mode: LineMode,
+    line_width: f32,
+    // Triangles in front of the camera in the last pass
+    triangle_count: usize,
+    translucent: Vec<TranslucentTriangle>,
+    shadows: Option<ShadowSettings>,
+    // One per light, None for the lights that cast no shadows
+    shadow_maps: Vec<Option<ShadowMap>>,
+    render_path: RenderPath,
+    gbuffer: GBuffer,
+    // Tessellations of the scene's spheres, built once instead of on every pass and shadow map
+    sphere_meshes: Vec<(Rc<Sphere>, Rc<Mesh>)>,
 }
 
 impl Rasterizer {
     pub const CANVAS: [i32; 2] = [ 1600, 1600 ];
     pub const VIEWPORT: [f32; 3] = [1.0, 1.0, 1.0];
-    pub const CAMERA_POSITION: [f32; 3] = [0.0, 0.0, 0.0];
+    pub const CAMERA_POSITION: Point3 = Point3::ORIGIN;
+    const NO_OWNER: u32 = u32::MAX;
+    const SPHERE_SEGMENTS: usize = 32;
+    const SPHERE_RINGS: usize = 16;
+
+    pub fn new(camera: Camera, projection: Projection) -> Self {
+        let cleared_depth = if matches!(projection, Projection::Perspective(_)) { 0.0 } else { f32::INFINITY };
+        let depth_buffer = vec![cleared_depth; (Self::CANVAS[0] * Self::CANVAS[1]) as usize];
+        let pixel_owners = vec![Self::NO_OWNER; (Self::CANVAS[0] * Self::CANVAS[1]) as usize];
+        Self {
+            state: vec![], canvas: Self::CANVAS, depth_buffer, pixel_owners, camera, projection, scene: None, lights: vec![],
+            mode: RenderMode::Filled, traversal: Traversal::Scanline, line_mode: LineMode::Aliased, line_width: 1.0,
+            triangle_count: 0, translucent: vec![], shadows: None, shadow_maps: vec![],
+            render_path: RenderPath::Forward, gbuffer: GBuffer::new(Self::CANVAS), sphere_meshes: vec![],
+        }
+    }
+
+    pub fn get_camera_mut(&mut self) -> &mut Camera {
+        return &mut self.camera;
+    }
+
+    pub fn get_projection(&self) -> &Projection {
+        return &self.projection;
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        let cleared_depth = self.get_cleared_depth();
+        self.depth_buffer.fill(cleared_depth);
+    }
+
+    pub fn is_perspective(&self) -> bool {
+        return matches!(self.projection, Projection::Perspective(_));
+    }
+
+    pub fn get_canvas_size(&self) -> [i32; 2] {
+        return self.canvas;
+    }
+
+    // Renders the same view at another resolution, the per-pixel buffers follow the new size
+    pub fn set_canvas_size(&mut self, canvas: [i32; 2]) {
+        self.canvas = canvas;
+        self.depth_buffer = vec![self.get_cleared_depth(); (canvas[0] * canvas[1]) as usize];
+        self.pixel_owners = vec![Self::NO_OWNER; (canvas[0] * canvas[1]) as usize];
+        self.gbuffer = GBuffer::new(canvas);
+    }
+
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    pub fn get_mode(&self) -> RenderMode {
+        return self.mode;
+    }
+
+    pub fn set_mode(&mut self, mode: RenderMode) {
+        self.mode = mode;
+    }
+
+    pub fn get_traversal(&self) -> Traversal {
+        return self.traversal;
+    }
+
+    pub fn set_traversal(&mut self, traversal: Traversal) {
+        self.traversal = traversal;
+    }
+
+    pub fn get_triangle_count(&self) -> usize {
+        return self.triangle_count;
+    }
+
+    pub fn get_line_mode(&self) -> LineMode {
+        return self.line_mode;
+    }
+
+    pub fn set_line_mode(&mut self, line_mode: LineMode) {
+        self.line_mode = line_mode;
+    }
+
+    pub fn get_line_width(&self) -> f32 {
+        return self.line_width;
+    }
+
+    // Width in pixels across the line, wireframes use it in both line modes
+    pub fn set_line_width(&mut self, line_width: f32) {
+        self.line_width = line_width.max(0.0);
+    }
+
+    pub fn get_shadows(&self) -> Option<ShadowSettings> {
+        return self.shadows;
+    }
+
+    // Directional and spot lights cast shadows in the shaded mode, which then lights every pixel on its own
+    pub fn set_shadows(&mut self, shadows: Option<ShadowSettings>) {
+        self.shadows = shadows;
+    }
 
-    pub fn new(viewport: Viewport, camera: Camera, projection: PerspectiveProjection) -> Self {
-        Self { state: vec![], viewport, camera, projection }
+    pub fn get_render_path(&self) -> RenderPath {
+        return self.render_path;
     }
 
-    fn render_triangle(&mut self, indices: [usize; 3], projection: Vec<[i32; 2]>, rgb: [f32; 3]) {
-        let point_a = projection[indices[0]];
-        let point_b = projection[indices[1]];
-        let point_c = projection[indices[2]];
-        self.draw_wireframe_triangle(point_a, point_b, point_c, rgb);
+    // Only the shaded mode has lighting to defer, the other modes always draw forward
+    pub fn set_render_path(&mut self, render_path: RenderPath) {
+        self.render_path = render_path;
     }
 
-    fn render_object(&mut self, vertices: Vec<[f32; 3]>, geometries: Vec<Triangle>, transform: [[f32; 4]; 4]) {
-        let mut projection: Vec<[i32; 2]> = vec![];
+    // Surfaces of the last deferred pass, empty after a forward one
+    pub fn get_gbuffer(&self) -> &GBuffer {
+        return &self.gbuffer;
+    }
+
+    // Keeps the scene so it can be rendered again after the camera moves
+    pub fn set_scene(&mut self, root: Node) {
+        self.sphere_meshes.clear();
+        self.scene = Some(root);
+    }
 
-        for vertex in vertices.iter() {
-            let cam_proj = multiply_mat4_mat4(self.camera.get_projection_mat4(), transform);
-            let vert_proj = multiply_mat4_vec(cam_proj, [vertex[0], vertex[1], vertex[2], 1.0]);
-            projection.push(self.project_vertex(&[vert_proj[0], vert_proj[1], vert_proj[2]]))
+    pub fn pass(&mut self) {
+        self.state.clear();
+        let cleared_depth = self.get_cleared_depth();
+        self.depth_buffer.fill(cleared_depth);
+        self.pixel_owners.fill(Self::NO_OWNER);
+        self.gbuffer.clear();
+        self.triangle_count = 0;
+
+        if let Some(root) = self.scene.take() {
+            self.render_shadow_maps(&root);
+            self.render_scene(&root);
+            self.scene = Some(root);
         }
-        for geometry in geometries.iter() {
-            self.render_triangle(geometry.group, projection.clone(), geometry.color);
+    }
+
+    fn render_triangle(&mut self, mesh: &Mesh, group: [usize; 3], projection: &[[f32; 2]], view: &[Point3], world: &[Point3], normals: Option<&[Vec3]>) {
+        let corners = group.map(|idx| Corner {
+            point: projection[idx],
+            view: view[idx],
+            world: world[idx],
+            normal: normals.map(|normals| normals[idx]),
+            uv: mesh.get_vertex_uv(idx),
+            color: mesh.get_vertex_color(idx),
+        });
+        let near = self.get_near_plane();
+        if corners.iter().all(|corner| corner.view.z >= near) {
+            self.render_corners(mesh, group, corners);
+            return;
+        }
+
+        // A corner behind the camera would project mirrored or unbounded, so the triangle is cut at the
+        // near plane first. What is left is a triangle or a quad, drawn as a fan
+        let clipped = self.clip_near(&corners, near);
+        for idx in 1 .. clipped.len().saturating_sub(1) {
+            self.render_corners(mesh, group, [clipped[0], clipped[idx], clipped[idx + 1]]);
         }
     }
 
-    pub fn project_vertex(&mut self, vertex: &[f32; 3]) -> [i32; 2] {
-        fn viewport_to_canvas(x: f32, y: f32) -> [i32; 2] {
-            let x_pos = x * Rasterizer::CANVAS[0] as f32 / Rasterizer::VIEWPORT[0] as f32;
-            let y_pos = y * Rasterizer::CANVAS[1] as f32 / Rasterizer::VIEWPORT[1] as f32;
-            return [x_pos as i32, y_pos as i32];
+    // Sutherland–Hodgman against the plane z = near in camera space. Edges are always cut from their
+    // corner in front, so an edge shared by two triangles is cut at the same point in both
+    fn clip_near(&self, corners: &[Corner; 3], near: f32) -> Vec<Corner> {
+        let mut clipped = Vec::with_capacity(4);
+        for idx in 0..3 {
+            let current = corners[idx];
+            let next = corners[(idx + 1) % 3];
+            let (current_in, next_in) = (current.view.z >= near, next.view.z >= near);
+            if current_in {
+                clipped.push(current);
+            }
+            if current_in != next_in {
+                let (inside, outside) = if current_in { (current, next) } else { (next, current) };
+                let t = (near - inside.view.z) / (outside.view.z - inside.view.z);
+                let mut crossing = inside.lerp(outside, t);
+                crossing.view.z = near;
+                crossing.point = self.project_vertex(&crossing.view);
+                clipped.push(crossing);
+            }
         }
+        return clipped;
+    }
 
-        let x_projection = vertex[0] * Rasterizer::VIEWPORT[2] / vertex[2];
-        let y_projection = vertex[1] * Rasterizer::VIEWPORT[2] / vertex[2];
+    // `group` is the mesh triangle the corners came from, it decides translucency and the wireframe color
+    fn render_corners(&mut self, mesh: &Mesh, group: [usize; 3], corners: [Corner; 3]) {
+        let points = corners.map(|corner| corner.point);
+        let depths = corners.map(|corner| corner.view.z);
 
-        return viewport_to_canvas(x_projection, y_projection);
+        self.triangle_count += 1;
+
+        let surface = match self.mode {
+            RenderMode::Wireframe | RenderMode::Filled => None,
+            RenderMode::Shaded => {
+                // Every pixel is lit from the surface under it, highlights and shadow edges fall inside
+                // triangles. The face normal lets coplanar triangles blend without a seam, the vertex normals
+                // of a curved surface let neighbouring faces blend into each other
+                let positions = corners.map(|corner| corner.world);
+                let face_normal = (positions[1] - positions[0]).cross(positions[2] - positions[0]).normalize();
+                let normals = corners.map(|corner| corner.normal.unwrap_or(face_normal));
+                Some(Surface { positions, normals })
+            },
+        };
+
+        // A texture replaces the vertex colors, apart from their alpha
+        let texture = mesh.get_texture();
+        let colors = corners.map(|corner| {
+            let base = if texture.is_some() { Color::WHITE } else { corner.color.to_color() };
+            Rgba::from_color(base, corner.color.a)
+        });
+        let uvs = corners.map(|corner| corner.uv);
+
+        match self.mode {
+            RenderMode::Wireframe => {
+                // Lines take the color of the triangle's first corner
+                let color = mesh.get_vertex_color(group[0]).to_color();
+                if self.line_mode == LineMode::Aliased && self.line_width == 1.0 {
+                    let [a, b, c] = points.map(|point| point.map(|value| value.round() as i32));
+                    self.draw_wireframe_triangle(a, b, c, color);
+                } else {
+                    let [a, b, c] = points;
+                    for (p, q) in [(a, b), (b, c), (c, a)] {
+                        self.draw_wide_line(p, q, color, self.line_width, self.line_mode);
+                    }
+                }
+            },
+            RenderMode::Filled | RenderMode::Shaded if mesh.is_translucent(group) => {
+                self.translucent.push(TranslucentTriangle {
+                    points, depths, uvs, texture: texture.cloned(), colors, blend_mode: mesh.get_blend_mode(), surface,
+                    specular: mesh.get_specular(),
+                });
+            },
+            RenderMode::Filled | RenderMode::Shaded => {
+                let shading = Shading {
+                    texture: texture.map(|texture| texture.as_ref()), blend_mode: None, surface, specular: mesh.get_specular(),
+                };
+                self.fill_triangle(points, depths, uvs, colors, shading);
+            },
+        }
+    }
+
+    // Blending does not commute, so translucent triangles go last and farthest first: each one mixes
+    // with everything behind it. They test against the depth buffer but leave it as it is, so a
+    // translucent triangle never hides another
+    fn render_translucent(&mut self) {
+        let mut triangles = std::mem::take(&mut self.translucent);
+        triangles.sort_by(|p, q| q.get_depth().total_cmp(&p.get_depth()));
+
+        for triangle in triangles.iter() {
+            let shading = Shading {
+                texture: triangle.texture.as_deref(), blend_mode: Some(triangle.blend_mode), surface: triangle.surface, specular: triangle.specular,
+            };
+            self.fill_triangle(triangle.points, triangle.depths, triangle.uvs, triangle.colors, shading);
+        }
+
+        // The buffer is kept for the next pass
+        triangles.clear();
+        self.translucent = triangles;
+    }
+
+    // Diffuse light and specular highlights with the raytracer's model, forward and deferred shading both
+    // light every pixel with it. Lights with a shadow map are dimmed by how much of them the position can see
+    fn compute_lighting(&self, position: Point3, normal: Vec3, specular: f32) -> f32 {
+        let to_camera = self.camera.get_position() - position;
+
+        return self.lights
+            .iter()
+            .enumerate()
+            .map(|(idx, light)| {
+                let light_vec = match light.mode {
+                    LightMode::Ambient => return light.intensity,
+                    LightMode::Point | LightMode::Spot(_) => light.position - position,
+                    LightMode::Directional => light.direction,
+                };
+                let light_to_surface = normal.dot(light_vec);
+                if !light.reaches(position) || light_to_surface <= 0.0 { return 0.0; }
+
+                let mut lit = light.intensity * light_to_surface / light_vec.length();
+                if specular > 0.0 {
+                    let reflection = normal * (2.0 * light_to_surface) - light_vec;
+                    let alignment = reflection.dot(to_camera) / (reflection.length() * to_camera.length());
+                    if alignment > 0.0 { lit += light.intensity * alignment.powf(specular); }
+                }
+                match self.shadow_maps.get(idx) {
+                    Some(Some(map)) => lit * map.get_visibility(position, normal, light_vec),
+                    _ => lit,
+                }
+            })
+            .sum();
+    }
+
+    fn is_deferred(&self) -> bool {
+        return self.mode == RenderMode::Shaded && self.render_path == RenderPath::Deferred;
+    }
+
+    // Lights every pixel left in the G-buffer once, whatever number of triangles were drawn over it
+    fn render_gbuffer(&mut self) {
+        let gbuffer = std::mem::replace(&mut self.gbuffer, GBuffer::new([0, 0]));
+        for (x, y, sample) in gbuffer.iter() {
+            let light = self.compute_lighting(sample.position, sample.normal, sample.specular);
+            self.put_pixel(x, y, (sample.albedo * light).clamp());
+        }
+        self.gbuffer = gbuffer;
+    }
+
+    // Depth-only pass from every light that casts shadows, over the box around the whole scene
+    fn render_shadow_maps(&mut self, root: &Node) {
+        self.shadow_maps.clear();
+        let Some(settings) = self.shadows else { return; };
+        if self.mode != RenderMode::Shaded { return; }
+        let Some(bounds) = Self::get_scene_bounds(root) else { return; };
+
+        let mut shadow_maps: Vec<Option<ShadowMap>> = self.lights.iter().map(|light| ShadowMap::new(light, bounds, settings)).collect();
+        for map in shadow_maps.iter_mut().flatten() {
+            root.traverse(Mat4::IDENTITY, &mut |shape, transform| {
+                match shape {
+                    Shape::Mesh(mesh) => Self::render_shadow_object(map, mesh, transform),
+                    Shape::Sphere(sphere) => {
+                        let mesh = self.get_sphere_mesh(sphere);
+                        Self::render_shadow_object(map, &mesh, transform * Mat4::translation(sphere.center.to_vec3()));
+                    },
+                }
+            });
+        }
+        self.shadow_maps = shadow_maps;
+    }
+
+    // World-space box around every shape, from the corners of each shape's own box
+    fn get_scene_bounds(root: &Node) -> Option<[Point3; 2]> {
+        let mut bounds: Option<[Point3; 2]> = None;
+        root.traverse(Mat4::IDENTITY, &mut |shape, transform| {
+            let local = match shape {
+                Shape::Mesh(mesh) => mesh.get_bounds(),
+                Shape::Sphere(sphere) => {
+                    let reach = Vec3::new(sphere.radius, sphere.radius, sphere.radius);
+                    Some([sphere.center - reach, sphere.center + reach])
+                },
+            };
+            let Some([min, max]) = local else { return; };
+            for corner in 0..8 {
+                let pick = |axis: usize, low: f32, high: f32| if corner & (1 << axis) == 0 { low } else { high };
+                let point = transform.transform_point(Point3::new(pick(0, min.x, max.x), pick(1, min.y, max.y), pick(2, min.z, max.z)));
+                let [low, high] = bounds.unwrap_or([point, point]);
+                bounds = Some([
+                    Point3::new(low.x.min(point.x), low.y.min(point.y), low.z.min(point.z)),
+                    Point3::new(high.x.max(point.x), high.y.max(point.y), high.z.max(point.z)),
+                ]);
+            }
+        });
+        return bounds;
+    }
+
+    // Translucent triangles let the light through, like they leave the depth buffer alone
+    fn render_shadow_object(map: &mut ShadowMap, mesh: &Mesh, transform: Mat4) {
+        let projected: Vec<Option<([f32; 2], f32)>> = mesh.get_vertices()
+            .iter()
+            .map(|&vertex| map.project(transform.transform_point(vertex)))
+            .collect();
+        for &group in mesh.get_indices().iter() {
+            if mesh.is_translucent(group) { continue; }
+            let [Some(a), Some(b), Some(c)] = group.map(|idx| projected[idx]) else { continue; };
+            Self::fill_shadow_triangle(map, [a.0, b.0, c.0], [a.1, b.1, c.1]);
+        }
+    }
+
+    fn fill_shadow_triangle(map: &mut ShadowMap, points: [[f32; 2]; 3], depths: [f32; 3]) {
+        let perspective = map.is_perspective();
+        let values = if perspective { depths.map(|depth| 1.0 / depth) } else { depths };
+        let gradient = Self::attribute_gradient(points, values);
+        let size = map.get_size();
+        for part in Self::clip_to_canvas(points, size) {
+            for (y, x_start, x_end) in Self::triangle_spans(part, size) {
+                for x in x_start ..= x_end {
+                    let value = Self::attribute_at(points[0], values[0], gradient, [x as f32, y as f32]);
+                    map.put_depth(x, y, if perspective { 1.0 / value } else { value });
+                }
+            }
+        }
+    }
+
+    fn render_object(&mut self, mesh: &Mesh, transform: Mat4) {
+        let mut projection: Vec<[f32; 2]> = vec![];
+        let mut view: Vec<Point3> = vec![];
+        let mut world: Vec<Point3> = vec![];
+        let normals = mesh.get_transformed_normals(transform);
+
+        // The mesh stays in local space, the model and view matrices are combined once for all its vertices
+        let model_view = self.camera.get_projection_mat4() * transform;
+        for vertex in mesh.get_vertices().iter() {
+            let vert_proj = model_view.transform_point(*vertex);
+            projection.push(self.project_vertex(&vert_proj));
+            view.push(vert_proj);
+            world.push(transform.transform_point(*vertex));
+        }
+        for &group in mesh.get_indices().iter() {
+            self.render_triangle(mesh, group, &projection, &view, &world, normals.as_deref());
+        }
+    }
+
+    // Camera space depth below which geometry is clipped away
+    fn get_near_plane(&self) -> f32 {
+        return match &self.projection {
+            Projection::Perspective(_) => Rasterizer::VIEWPORT[2],
+            Projection::Orthographic(orthographic) => orthographic.get_clipping_planes().0,
+        }
+    }
+
+    // Spheres are tessellated the first time they are drawn and found again by the identity of their Rc,
+    // the raytracer keeps intersecting the exact sphere
+    fn get_sphere_mesh(&mut self, sphere: &Rc<Sphere>) -> Rc<Mesh> {
+        if let Some((_, mesh)) = self.sphere_meshes.iter().find(|(cached, _)| Rc::ptr_eq(cached, sphere)) {
+            return mesh.clone();
+        }
+
+        let mut mesh = primitives::uv_sphere(sphere.radius, Self::SPHERE_SEGMENTS, Self::SPHERE_RINGS, sphere.color);
+        if let Some(texture) = &sphere.texture {
+            mesh.set_texture(texture.clone());
+        }
+        mesh.set_specular(sphere.specular);
+        let mesh = Rc::new(mesh);
+        self.sphere_meshes.push((sphere.clone(), mesh.clone()));
+        return mesh;
+    }
+
+    pub fn render_scene(&mut self, root: &Node) {
+        root.traverse(Mat4::IDENTITY, &mut |shape, transform| {
+            match shape {
+                Shape::Mesh(mesh) => self.render_object(mesh, transform),
+                Shape::Sphere(sphere) => {
+                    let mesh = self.get_sphere_mesh(sphere);
+                    self.render_object(&mesh, transform * Mat4::translation(sphere.center.to_vec3()));
+                },
+            }
+        });
+        if self.is_deferred() {
+            self.render_gbuffer();
+        }
+        self.render_translucent();
+    }
+
+    // Canvas position with sub-pixel precision, pixel centers sit on whole numbers
+    pub fn project_vertex(&self, vertex: &Point3) -> [f32; 2] {
+        fn viewport_to_canvas(x: f32, y: f32, canvas: [i32; 2]) -> [f32; 2] {
+            let x_pos = x * canvas[0] as f32 / Rasterizer::VIEWPORT[0];
+            let y_pos = y * canvas[1] as f32 / Rasterizer::VIEWPORT[1];
+            return [x_pos, y_pos];
+        }
+
+        return match &self.projection {
+            Projection::Perspective(_) => {
+                let x_projection = vertex.x * Rasterizer::VIEWPORT[2] / vertex.z;
+                let y_projection = vertex.y * Rasterizer::VIEWPORT[2] / vertex.z;
+                viewport_to_canvas(x_projection, y_projection, self.canvas)
+            },
+            Projection::Orthographic(orthographic) => orthographic.window_to_canvas(*vertex, self.canvas),
+        }
     }
 
     pub fn get_state(&mut self) -> &[Vertex] {
         return self.state.as_slice();
     }
 
-    pub fn put_pixel(&mut self, x: i32, y: i32, rgb: [f32; 3]) {
-        let x_cord = x as f32 / (Self::CANVAS[0] / 2) as f32;
-        let y_cord = y as f32 / (Self::CANVAS[1] / 2) as f32;
-        let color = [ rgb[0] / 255.0, rgb[1] / 255.0, rgb[2] / 255.0 ];
+    pub fn get_frame(&self) -> Frame {
+        return Frame::from_vertices(&self.state, self.canvas, BACKGROUND);
+    }
+
+    pub fn put_pixel(&mut self, x: i32, y: i32, rgb: Color) {
+        let x_cord = x as f32 / (self.canvas[0] / 2) as f32;
+        let y_cord = y as f32 / (self.canvas[1] / 2) as f32;
+        let color = rgb.to_normalized();
+        if let Some(idx) = self.pixel_index(x, y) {
+            self.pixel_owners[idx] = self.state.len() as u32;
+        }
         self.state.push(Vertex { position: [x_cord, y_cord, 0.0], color });
     }
 
-    pub fn draw_line(&mut self, point_a: [i32; 2], point_b: [i32; 2], rgb: [f32; 3]) {
-        let mut a = point_a;
-        let mut b = point_b;
+    // Color showing at a pixel so far in this pass, the background where nothing was drawn
+    pub fn get_pixel(&self, x: i32, y: i32) -> Color {
+        return match self.pixel_index(x, y).map(|idx| self.pixel_owners[idx]) {
+            Some(owner) if owner != Self::NO_OWNER => Color::from(self.state[owner as usize].color) * 255.0,
+            _ => BACKGROUND,
+        }
+    }
 
-        if (b[0] - a[0]).abs() > (b[1] - a[1]).abs() {
-            if a[0] > b[0] { std::mem::swap(&mut a, &mut b); }
-            let y_values = interpolate(a[0], a[1] as f32, b[0], b[1] as f32);
-            for x in a[0] ..= b[0] {
-                let y_idx = (x - a[0]) as usize;
-                self.put_pixel(x, y_values[y_idx], rgb);
-            }
-        } else {
-            if a[1] > b[1] { std::mem::swap(&mut a, &mut b); }
-            let x_values = interpolate(a[1], a[0] as f32, b[1], b[0] as f32);
-            for y in a[1] ..= b[1] {
-                let x_idx = (y - a[1]) as usize;
-                self.put_pixel(x_values[x_idx], y, rgb);
+    // Mixes the color with the pixel by its alpha, an opaque color blended Over paints it like put_pixel.
+    // The new point carries the mixed color, so the window still draws it without blending
+    pub fn blend_pixel(&mut self, x: i32, y: i32, rgba: Rgba, mode: BlendMode) {
+        if rgba.a <= 0.0 { return; }
+        let blended = mode.blend(self.get_pixel(x, y), rgba);
+        self.put_pixel(x, y, blended);
+    }
+
+    fn pixel_index(&self, x: i32, y: i32) -> Option<usize> {
+        let x_idx = x + self.canvas[0] / 2;
+        let y_idx = y + self.canvas[1] / 2;
+        if x_idx < 0 || x_idx >= self.canvas[0] || y_idx < 0 || y_idx >= self.canvas[1] {
+            return None;
+        }
+        return Some((y_idx * self.canvas[0] + x_idx) as usize);
+    }
+
+    // The depth buffer holds 1/z under perspective, where nearer is larger, and z itself under an
+    // orthographic projection, where nearer is smaller. Both are linear in screen space
+    fn get_cleared_depth(&self) -> f32 {
+        return if self.is_perspective() { 0.0 } else { f32::INFINITY };
+    }
+
+    fn is_nearer(&self, depth: f32, stored: f32) -> bool {
+        return if self.is_perspective() { depth > stored } else { depth < stored };
+    }
+
+    fn is_depth_visible(&self, x: i32, y: i32, depth: f32) -> bool {
+        return match self.pixel_index(x, y) {
+            Some(idx) => self.is_nearer(depth, self.depth_buffer[idx]),
+            None => false,
+        }
+    }
+
+    fn update_depth_buffer(&mut self, x: i32, y: i32, depth: f32) -> bool {
+        let Some(idx) = self.pixel_index(x, y) else { return false; };
+        if !self.is_nearer(depth, self.depth_buffer[idx]) {
+            return false;
+        }
+        self.depth_buffer[idx] = depth;
+        return true;
+    }
+
+    pub fn draw_line(&mut self, point_a: [i32; 2], point_b: [i32; 2], rgb: Color) {
+        drawing::draw_line(self, point_a, point_b, rgb);
+    }
+
+    // Xiaolin Wu's line with sub-pixel endpoints, each pixel is blended by how much of it the line covers
+    pub fn draw_line_antialiased(&mut self, point_a: [f32; 2], point_b: [f32; 2], rgb: Color, width: f32) {
+        self.draw_wide_line(point_a, point_b, rgb, width, LineMode::AntiAliased);
+    }
+
+    // Aliased lines keep the pixels at least half covered, so a wide line stays as thick as the anti-aliased one
+    fn draw_wide_line(&mut self, point_a: [f32; 2], point_b: [f32; 2], rgb: Color, width: f32, line_mode: LineMode) {
+        let canvas = self.canvas;
+        Self::line_coverage(point_a, point_b, width, canvas, |x, y, coverage| match line_mode {
+            LineMode::AntiAliased => self.blend_pixel(x, y, Rgba::from_color(rgb, coverage), BlendMode::Over),
+            LineMode::Aliased => if coverage >= 0.5 { self.put_pixel(x, y, rgb) },
+        });
+    }
+
+    // Visits the pixels under a line of the given width with the share of each pixel it covers.
+    // Like Wu's algorithm it steps along the major axis and splits the line between the pixels across
+    // it, here by how much of each pixel the line's span overlaps: at width 1 that is Wu's weighting,
+    // wider lines fill the pixels in between. The ends are cut square at the endpoints
+    fn line_coverage(point_a: [f32; 2], point_b: [f32; 2], width: f32, canvas: [i32; 2], mut visit: impl FnMut(i32, i32, f32)) {
+        let steep = (point_b[1] - point_a[1]).abs() > (point_b[0] - point_a[0]).abs();
+        // Steep lines are walked with x and y swapped
+        let swap = |point: [f32; 2]| if steep { [point[1], point[0]] } else { point };
+        let (mut a, mut b) = (swap(point_a), swap(point_b));
+        if a[0] > b[0] { std::mem::swap(&mut a, &mut b); }
+
+        let [major_half, minor_half] = if steep { [canvas[1] / 2, canvas[0] / 2] } else { [canvas[0] / 2, canvas[1] / 2] };
+        let gradient = if b[0] > a[0] { (b[1] - a[1]) / (b[0] - a[0]) } else { 0.0 };
+        // Span across the major axis that gives the line its width measured square to its direction
+        let half_span = width * (1.0 + gradient * gradient).sqrt() / 2.0;
+        if !half_span.is_finite() || half_span <= 0.0 { return; }
+
+        let first = ((a[0] + 0.5).floor() as i32).max(-major_half);
+        let last = ((b[0] + 0.5).ceil() as i32 - 1).min(major_half - 1);
+        for major in first ..= last {
+            let position = major as f32;
+            let along = (position + 0.5).min(b[0]) - (position - 0.5).max(a[0]);
+            let center = a[1] + gradient * (position - a[0]);
+            let (low, high) = (center - half_span, center + half_span);
+
+            let first_minor = ((low + 0.5).floor() as i32).max(-minor_half);
+            let last_minor = ((high + 0.5).ceil() as i32 - 1).min(minor_half - 1);
+            for minor in first_minor ..= last_minor {
+                let across = (minor as f32 + 0.5).min(high) - (minor as f32 - 0.5).max(low);
+                let coverage = (along * across).min(1.0);
+                if coverage <= 0.0 { continue; }
+                if steep { visit(minor, major, coverage) } else { visit(major, minor, coverage) }
             }
         }
     }
 
-    pub fn draw_wireframe_triangle(&mut self, point_a: [i32; 2], point_b: [i32; 2], point_c: [i32; 2], rgb: [f32; 3]) {
+    pub fn draw_wireframe_triangle(&mut self, point_a: [i32; 2], point_b: [i32; 2], point_c: [i32; 2], rgb: Color) {
         self.draw_line(point_a, point_b, rgb);
         self.draw_line(point_b, point_c, rgb);
         self.draw_line(point_c, point_a, rgb);
     }
 
-    pub fn draw_filled_triangle(&mut self, point_a: [i32; 2], point_b: [i32; 2], point_c: [i32; 2], rgb: [f32; 3]) {
-        let mut a = point_a;
-        let mut b = point_b;
-        let mut c = point_c;
+    pub fn draw_filled_triangle(&mut self, point_a: [i32; 2], point_b: [i32; 2], point_c: [i32; 2], rgb: Color) {
+        let points = [point_a, point_b, point_c].map(|point| [point[0] as f32, point[1] as f32]);
+        let shade: [f32; 3] = [0.1, 0.0, 1.0];
+        let shade_gradient = Self::attribute_gradient(points, shade);
+
+        for (y, x_start, x_end) in Self::triangle_spans(points, self.canvas) {
+            for x in x_start ..= x_end {
+                let shade_factor = Self::attribute_at(points[0], shade[0], shade_gradient, [x as f32, y as f32]);
+                self.put_pixel(x, y, (rgb * shade_factor).clamp());
+            }
+        }
+    }
+
+    // X where the edge from p to q crosses row y, p is always the lower end so a shared edge gives the same value in both triangles
+    fn edge_x(p: [f32; 2], q: [f32; 2], y: f32) -> f32 {
+        return p[0] + (y - p[1]) * (q[0] - p[0]) / (q[1] - p[1]);
+    }
+
+    // Rows of pixels covered by a triangle as (y, first x, last x), limited to the canvas.
+    // A pixel center exactly on an edge belongs to the triangle only on a top or left edge (y grows
+    // upwards here, so top is the larger y), which keeps shared edges free of gaps and double draws
+    fn triangle_spans(points: [[f32; 2]; 3], canvas: [i32; 2]) -> impl Iterator<Item = (i32, i32, i32)> {
+        let mut sorted = points;
+        sorted.sort_by(|p, q| p[1].total_cmp(&q[1]));
+        let [a, b, c] = sorted;
+
+        // Positive when b lies right of the long edge a-c, zero for a triangle without area
+        let orientation = (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1]);
+        let [half_width, half_height] = [canvas[0] / 2, canvas[1] / 2];
+        let (first_row, last_row) = if orientation == 0.0 || !orientation.is_finite() {
+            (1, 0)
+        } else {
+            ((a[1].floor() as i32 + 1).max(-half_height), (c[1].floor() as i32).min(half_height - 1))
+        };
+
+        return (first_row ..= last_row).filter_map(move |y| {
+            let row = y as f32;
+            let x_long = Self::edge_x(a, c, row);
+            let x_short = if row <= b[1] { Self::edge_x(a, b, row) } else { Self::edge_x(b, c, row) };
+            let (x_left, x_right) = if orientation > 0.0 { (x_long, x_short) } else { (x_short, x_long) };
+
+            let x_start = (x_left.ceil() as i32).max(-half_width);
+            let x_end = (x_right.ceil() as i32 - 1).min(half_width - 1);
+            if x_start > x_end { None } else { Some((y, x_start, x_end)) }
+        });
+    }
+
+    fn attribute_at(origin: [f32; 2], value: f32, gradient: [f32; 2], point: [f32; 2]) -> f32 {
+        return value + gradient[0] * (point[0] - origin[0]) + gradient[1] * (point[1] - origin[1]);
+    }
+
+    // A triangle reaching past the canvas is cut to it and split into a fan, so the traversals never see
+    // the huge coordinates of vertices close to the camera. Fan triangles share their inner edges exactly
+    fn clip_to_canvas(points: [[f32; 2]; 3], canvas: [i32; 2]) -> Vec<[[f32; 2]; 3]> {
+        let bounds = canvas_bounds(canvas);
+        let [min, max] = bounds;
+        if points.iter().all(|point| point[0] >= min[0] && point[0] <= max[0] && point[1] >= min[1] && point[1] <= max[1]) {
+            return vec![points];
+        }
+
+        let polygon = clip_polygon(&points, bounds);
+        return (1 .. polygon.len().saturating_sub(1))
+            .map(|idx| [polygon[0], polygon[idx], polygon[idx + 1]])
+            .collect();
+    }
+
+    const SUBPIXEL: f32 = 256.0;
+    // Keeps the products of fixed-point coordinates inside i64
+    const MAX_FIXED_POINT_COORDINATE: f32 = (1 << 22) as f32;
 
-        if b[1] < a[1] { std::mem::swap(&mut b, &mut a); }
-        if c[1] < a[1] { std::mem::swap(&mut c, &mut a); }
-        if c[1] < b[1] { std::mem::swap(&mut c, &mut b); }
+    fn fits_fixed_point(points: [[f32; 2]; 3]) -> bool {
+        return points.iter().flatten().all(|value| value.abs() < Self::MAX_FIXED_POINT_COORDINATE);
+    }
 
-        let mut shade: [f32; 3] = [10.0, 0.0, 100.0];
+    // Visits every pixel inside a triangle with its barycentric weights for the three vertices.
+    // Vertices snap to fixed point so the edge functions are exact integers: an edge shared by two
+    // triangles gives the same values with opposite signs, and the top-left rule settles pixels at zero
+    fn edge_function_coverage(points: [[f32; 2]; 3], canvas: [i32; 2], mut visit: impl FnMut(i32, i32, [f32; 3])) {
+        const TILE: i32 = 8;
 
-        let mut x_vals_a_to_b = interpolate(a[1], a[0] as f32, b[1], b[0] as f32);
-        let mut shade_a_to_b = interpolate(a[1], shade[0], b[1], shade[1]);
+        let fixed = points.map(|point| point.map(|value| (value * Self::SUBPIXEL).round() as i64));
+        let area = (fixed[1][0] - fixed[0][0]) * (fixed[2][1] - fixed[0][1]) - (fixed[2][0] - fixed[0][0]) * (fixed[1][1] - fixed[0][1]);
+        if area == 0 { return; }
+        // Counter-clockwise order puts the inside on the positive side of every edge
+        let [a, b, c] = if area > 0 { fixed } else { [fixed[0], fixed[2], fixed[1]] };
+        let order = if area > 0 { [0, 1, 2] } else { [0, 2, 1] };
+        let area = area.abs() as f32;
 
-        let mut x_vals_b_to_c = interpolate(b[1], b[0] as f32, c[1], c[0] as f32);
-        let mut shade_b_to_c = interpolate(b[1], shade[1], c[1], shade[2]);
+        // Edge i is opposite vertex i, so its value over the area is that vertex's barycentric weight
+        let edges = [(b, c), (c, a), (a, b)].map(|(p, q)| {
+            let step_x = p[1] - q[1];
+            let step_y = q[0] - p[0];
+            // Left edges run downwards, top edges run in -x since y grows upwards
+            let top_left = step_x > 0 || (step_x == 0 && step_y < 0);
+            (step_x, step_y, p, if top_left { 0 } else { 1 })
+        });
+        let edge_at = |edge: (i64, i64, [i64; 2], i64), x: i32, y: i32| {
+            let (step_x, step_y, p, _) = edge;
+            step_x * (x as i64 * Self::SUBPIXEL as i64 - p[0]) + step_y * (y as i64 * Self::SUBPIXEL as i64 - p[1])
+        };
 
-        let mut x_vals_a_to_c = interpolate(a[1], a[0] as f32, c[1], c[0] as f32);
-        let mut shade_a_to_c = interpolate(a[1], shade[0], c[1], shade[2]);
+        let [half_width, half_height] = [canvas[0] / 2, canvas[1] / 2];
+        let min = |axis: usize| a[axis].min(b[axis]).min(c[axis]);
+        let max = |axis: usize| a[axis].max(b[axis]).max(c[axis]);
+        let to_pixel = |value: i64| value.div_euclid(Self::SUBPIXEL as i64) as i32;
+        let (x_min, x_max) = ((to_pixel(min(0))).max(-half_width), (to_pixel(max(0)) + 1).min(half_width - 1));
+        let (y_min, y_max) = ((to_pixel(min(1))).max(-half_height), (to_pixel(max(1)) + 1).min(half_height - 1));
 
-        let _ = x_vals_a_to_b.pop();
-        let short_sides = [&x_vals_a_to_b[..], &x_vals_b_to_c[..]].concat();
+        for tile_y in (y_min ..= y_max).step_by(TILE as usize) {
+            for tile_x in (x_min ..= x_max).step_by(TILE as usize) {
+                let tile_x_end = (tile_x + TILE - 1).min(x_max);
+                let tile_y_end = (tile_y + TILE - 1).min(y_max);
 
-        let _ = shade_a_to_b.pop();
-        let shade_short_sides = [&shade_a_to_b[..], &shade_b_to_c[..]].concat();
+                // An edge function is linear, so a tile with every corner outside one edge is empty
+                let outside = edges.iter().any(|&edge| {
+                    [(tile_x, tile_y), (tile_x_end, tile_y), (tile_x, tile_y_end), (tile_x_end, tile_y_end)]
+                        .iter()
+                        .all(|&(x, y)| edge_at(edge, x, y) < edge.3)
+                });
+                if outside { continue; }
 
-        let mid_idx = short_sides.len() / 2;
-        let short_sides_on_the_right = x_vals_a_to_c[mid_idx] < short_sides[mid_idx];
-        let (x_left, x_right, shade_left, shade_right) =
-            if short_sides_on_the_right {
-                (x_vals_a_to_c, short_sides, shade_a_to_c, shade_short_sides)
+                for y in tile_y ..= tile_y_end {
+                    let mut values = edges.map(|edge| edge_at(edge, tile_x, y));
+                    for x in tile_x ..= tile_x_end {
+                        if (0..3).all(|i| values[i] >= edges[i].3) {
+                            let weights = values.map(|value| value as f32 / area);
+                            let mut ordered = [0.0; 3];
+                            for i in 0..3 { ordered[order[i]] = weights[i]; }
+                            visit(x, y, ordered);
+                        }
+                        for i in 0..3 { values[i] += edges[i].0 * Self::SUBPIXEL as i64; }
+                    }
+                }
             }
-            else {
-                (short_sides, x_vals_a_to_c, shade_short_sides, shade_a_to_c)
-            };
+        }
+    }
 
-        for y in a[1] ..= c[1] {
-            let inverse_y_idx = (y - a[1]) as usize;
-            let x_left_edge = x_left[inverse_y_idx];
-            let x_right_edge = x_right[inverse_y_idx];
+    // Partial derivatives (d/dx, d/dy) of an attribute that is linear over the screen-space triangle
+    fn attribute_gradient(points: [[f32; 2]; 3], values: [f32; 3]) -> [f32; 2] {
+        let [p0, p1, p2] = points;
+        let denominator = (p1[0] - p0[0]) * (p2[1] - p0[1]) - (p2[0] - p0[0]) * (p1[1] - p0[1]);
+        if denominator == 0.0 { return [0.0, 0.0]; }
 
-            let x_shades = interpolate(
-                x_left_edge,
-                shade_left[inverse_y_idx] as f32,
-                x_right_edge,
-                shade_right[inverse_y_idx] as f32,
-            );
+        let d_dx = ((values[1] - values[0]) * (p2[1] - p0[1]) - (values[2] - values[0]) * (p1[1] - p0[1])) / denominator;
+        let d_dy = ((values[2] - values[0]) * (p1[0] - p0[0]) - (values[1] - values[0]) * (p2[0] - p0[0])) / denominator;
+        return [d_dx, d_dy];
+    }
 
-            for x in x_left_edge ..= x_right_edge {
-                let inverse_x_idx = (x - x_left_edge) as usize;
+    pub fn draw_textured_triangle(&mut self, points: [[f32; 2]; 3], depths: [f32; 3], uvs: [[f32; 2]; 3], texture: &Texture) {
+        let shading = Shading { texture: Some(texture), ..Shading::default() };
+        self.fill_triangle(points, depths, uvs, [Rgba::opaque(Color::WHITE); 3], shading);
+    }
+
+    // Depth-tested fill with the colors interpolated from the corners, a texture is multiplied by them.
+    // Every attribute is evaluated at the pixel itself, so nothing drifts along long edges. Triangles
+    // with a blend mode mix with what is already drawn
+    fn fill_triangle(&mut self, points: [[f32; 2]; 3], depths: [f32; 3], uvs: [[f32; 2]; 3], colors: [Rgba; 3], shading: Shading) {
+        // Under perspective u/z, v/z and 1/z are linear in screen space, u and v are not, so attributes are
+        // weighted by 1/z and divided by it again per pixel. Orthographic screen space is affine in the view
+        // space, there the weight is 1 and the depth is z itself
+        let perspective = self.is_perspective();
+        let weights = if perspective { depths.map(|depth| 1.0 / depth) } else { [1.0; 3] };
+        let depth_values = if perspective { weights } else { depths };
+        let u_weighted = [0, 1, 2].map(|idx| uvs[idx][0] * weights[idx]);
+        let v_weighted = [0, 1, 2].map(|idx| uvs[idx][1] * weights[idx]);
+        let attributes = [
+            depth_values, weights, u_weighted, v_weighted,
+            colors.map(|color| color.r), colors.map(|color| color.g), colors.map(|color| color.b), colors.map(|color| color.a),
+        ];
+
+        // The gradients also give the UV footprint of a pixel for mip selection
+        let gradients = attributes.map(|values| Self::attribute_gradient(points, values));
+        let planes = shading.surface.map(|surface| SurfacePlanes::new(points, weights, surface));
 
-                let shade_factor: f32 = x_shades[inverse_x_idx] as f32 / 100.0;
-                let shaded_color = multiply_color(rgb, shade_factor);
-                self.put_pixel(x, y, shaded_color);
+        let origin = points[0];
+        let canvas = self.canvas;
+        for part in Self::clip_to_canvas(points, canvas) {
+            if self.traversal == Traversal::EdgeFunction && Self::fits_fixed_point(part) {
+                // Corners added by clipping read their attributes off the planes of the whole triangle
+                let part_attributes = if part == points {
+                    attributes
+                } else {
+                    std::array::from_fn(|idx| part.map(|point| Self::attribute_at(origin, attributes[idx][0], gradients[idx], point)))
+                };
+                Self::edge_function_coverage(part, canvas, |x, y, weights| {
+                    let values = part_attributes.map(|values| weights[0] * values[0] + weights[1] * values[1] + weights[2] * values[2]);
+                    self.shade_pixel(x, y, values, gradients, &shading, planes.as_ref());
+                });
+            } else {
+                for (y, x_start, x_end) in Self::triangle_spans(part, canvas) {
+                    for x in x_start ..= x_end {
+                        let values = std::array::from_fn(|idx| Self::attribute_at(origin, attributes[idx][0], gradients[idx], [x as f32, y as f32]));
+                        self.shade_pixel(x, y, values, gradients, &shading, planes.as_ref());
+                    }
+                }
             }
         }
     }
+
+    // Attributes are the depth, the weight, the weighted UV and the color with alpha at the pixel, with their screen-space
+    // gradients. Blended pixels are only depth tested and mix with the pixel instead of replacing it.
+    // Shaded triangles come with the planes of their surface, and are only lit once visible. Opaque
+    // pixels of a deferred pass go to the G-buffer unlit instead of the canvas
+    fn shade_pixel(&mut self, x: i32, y: i32, attributes: [f32; 8], gradients: [[f32; 2]; 8], shading: &Shading, planes: Option<&SurfacePlanes>) {
+        let [depth, weight, u_weighted, v_weighted, r, g, b, alpha] = attributes;
+        let visible = match shading.blend_mode {
+            Some(_) => self.is_depth_visible(x, y, depth),
+            None => self.update_depth_buffer(x, y, depth),
+        };
+        if !visible { return; }
+
+        let surface = planes.map(|planes| planes.at([x as f32, y as f32], weight));
+        let tint = Color::new(r, g, b);
+        let albedo = match shading.texture {
+            Some(texture) => {
+                let [_, weight_gradient, u_gradient, v_gradient, ..] = gradients;
+                let uv = [u_weighted / weight, v_weighted / weight];
+                let uv_at = |step: usize| {
+                    let weight = weight + weight_gradient[step];
+                    [(u_weighted + u_gradient[step]) / weight, (v_weighted + v_gradient[step]) / weight]
+                };
+                let [uv_x, uv_y] = [uv_at(0), uv_at(1)];
+                let lod = texture.get_lod([uv_x[0] - uv[0], uv_x[1] - uv[1]], [uv_y[0] - uv[0], uv_y[1] - uv[1]]);
+                texture.sample_lod(uv, lod) * tint
+            },
+            None => tint,
+        };
+
+        if let (Some((position, normal)), None, true) = (surface, shading.blend_mode, self.is_deferred()) {
+            self.gbuffer.put_sample(x, y, Sample { position, normal, albedo: albedo.clamp(), specular: shading.specular });
+            return;
+        }
+        let light = match surface {
+            Some((position, normal)) => self.compute_lighting(position, normal, shading.specular),
+            None => 1.0,
+        };
+        let color = (albedo * light).clamp();
+        match shading.blend_mode {
+            Some(blend_mode) => self.blend_pixel(x, y, Rgba::from_color(color, alpha), blend_mode),
+            None => self.put_pixel(x, y, color),
+        }
+    }
+}
+
+impl Canvas for Rasterizer {
+    fn get_canvas_size(&self) -> [i32; 2] {
+        return self.canvas;
+    }
+
+    fn put_pixel(&mut self, x: i32, y: i32, rgb: Color) {
+        Rasterizer::put_pixel(self, x, y, rgb);
+    }
 }
 
 pub fn init_rasterizer() -> Rasterizer {
-    let viewport = Viewport::new(1600.0, 1600.0);
-    let camera = Camera::new([0.0, 0.0, 0.0], None);
-    let projection = PerspectiveProjection::new(60.0, 1.0, 10.0, 70.0);
-    let mut rasterizer = Rasterizer::new(viewport, camera, projection);
+    return init_rasterizer_with_canvas(Rasterizer::CANVAS);
+}
+
+// The built-in scene at any resolution, used by the viewer and by the golden image tests
+pub fn init_rasterizer_with_canvas(canvas: [i32; 2]) -> Rasterizer {
+    let camera = Camera::new(Point3::ORIGIN, None);
+    let projection = Projection::Perspective(PerspectiveProjection::new(60.0, 1.0, 10.0, 70.0));
+    let mut rasterizer = Rasterizer::new(camera, projection);
+    rasterizer.set_canvas_size(canvas);
 
-    let mut box_a = Box::new([1.0, 1.0, 1.0], [-1.5, 0.0, 7.0], None);
-    let (box_a_position, box_a_triangles) = box_a.get_geometry();
-    let box_a_transform = box_a.get_model_mat4();
-    rasterizer.render_object(box_a_position, box_a_triangles, box_a_transform);
+    let mut checkerboard = Texture::checkerboard(64, 4, Color::WHITE, Color::new(40.0, 40.0, 40.0));
+    checkerboard.set_sampling(Filter::Trilinear, WrapMode::Repeat);
+    let checkerboard = Rc::new(checkerboard);
 
-    let mut box_b = Box::new([1.0, 1.0, 1.0], [1.25, 2.0, 7.5], None);
-    let (box_b_position, box_b_triangles) = box_b.get_geometry();
-    let box_b_transform = box_b.get_model_mat4();
-    rasterizer.render_object(box_b_position, box_b_triangles, box_b_transform);
+    let mut cube = Box::get_mesh();
+    cube.set_texture(checkerboard);
+    let cube = Rc::new(cube);
+
+    let box_a = Box::new(Vec3::new(1.0, 1.0, 1.0), Point3::new(-1.5, 0.0, 7.0), None);
+    let box_b = Box::new(Vec3::new(1.0, 1.0, 1.0), Point3::new(1.25, 2.0, 7.5), Some(Rotation::Euler([20.0, 30.0, 0.0], EulerOrder::Xyz)));
+
+    let mut root = Node::default();
+    root.add_child(Node::new(box_a.get_model_mat4(), Some(Shape::Mesh(cube.clone()))));
+    root.add_child(Node::new(box_b.get_model_mat4(), Some(Shape::Mesh(cube))));
+    rasterizer.set_scene(root);
+
+    rasterizer.pass();
 
     return rasterizer;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use proptest::prelude::*;
+    use crate::projection::OrthographicProjection;
+
+    const CANVAS: [i32; 2] = [200, 200];
+
+    fn coverage_with(traversal: Traversal, triangles: &[[[f32; 2]; 3]]) -> HashMap<(i32, i32), usize> {
+        let mut covered = HashMap::new();
+        for &triangle in triangles {
+            match traversal {
+                Traversal::Scanline => {
+                    for (y, x_start, x_end) in Rasterizer::triangle_spans(triangle, CANVAS) {
+                        for x in x_start ..= x_end {
+                            *covered.entry((x, y)).or_insert(0) += 1;
+                        }
+                    }
+                },
+                Traversal::EdgeFunction => {
+                    Rasterizer::edge_function_coverage(triangle, CANVAS, |x, y, _| {
+                        *covered.entry((x, y)).or_insert(0) += 1;
+                    });
+                },
+            }
+        }
+        return covered;
+    }
+
+    fn traversal() -> impl Strategy<Value = Traversal> {
+        prop_oneof![Just(Traversal::Scanline), Just(Traversal::EdgeFunction)]
+    }
+
+    fn pixel_corner() -> impl Strategy<Value = [f32; 2]> {
+        [-60i32..60, -60i32..60].prop_map(|corner| corner.map(|value| value as f32))
+    }
+
+    // Corners on an ellipse in angle order always form a convex quad
+    fn convex_quad() -> impl Strategy<Value = [[f32; 2]; 4]> {
+        let center = [-40.0f32..40.0, -40.0f32..40.0];
+        let radii = [5.0f32..60.0, 5.0f32..60.0];
+        (center, radii, [0.0f32..1.0, 0.0f32..1.0, 0.0f32..1.0, 0.0f32..1.0]).prop_map(|(center, radii, mut angles)| {
+            angles.sort_by(f32::total_cmp);
+            angles.map(|t| {
+                let angle = t * std::f32::consts::TAU;
+                [center[0] + radii[0] * angle.cos(), center[1] + radii[1] * angle.sin()]
+            })
+        })
+    }
+
+    fn line_coverage(a: [f32; 2], b: [f32; 2], width: f32) -> HashMap<(i32, i32), f32> {
+        let mut covered = HashMap::new();
+        Rasterizer::line_coverage(a, b, width, CANVAS, |x, y, coverage| {
+            covered.insert((x, y), coverage);
+        });
+        return covered;
+    }
+
+    fn distance_to_edges(triangle: [[f32; 2]; 3], point: [f32; 2]) -> f32 {
+        return [(0, 1), (1, 2), (2, 0)]
+            .iter()
+            .map(|&(p, q)| {
+                let [p, q] = [triangle[p], triangle[q]];
+                let cross = (q[0] - p[0]) * (point[1] - p[1]) - (q[1] - p[1]) * (point[0] - p[0]);
+                cross.abs() / ((q[0] - p[0]).powi(2) + (q[1] - p[1]).powi(2)).sqrt()
+            })
+            .fold(f32::INFINITY, f32::min);
+    }
+
+    fn large_point() -> impl Strategy<Value = [f32; 2]> {
+        [-2000.0f32..2000.0, -2000.0f32..2000.0]
+    }
+
+    fn line_end() -> impl Strategy<Value = [f32; 2]> {
+        [-60.0f32..60.0, -60.0f32..60.0]
+    }
+
+    proptest! {
+        // Pixels are unit squares, so the coverage of a line far from the canvas border adds up to its area
+        #[test]
+        fn line_coverage_adds_up_to_the_line_area(a in line_end(), b in line_end(), width in 0.25f32..4.0) {
+            let covered = line_coverage(a, b, width);
+            let area = ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt() * width;
+            let total: f32 = covered.values().sum();
+
+            prop_assert!(covered.values().all(|&coverage| coverage > 0.0 && coverage <= 1.0));
+            prop_assert!((total - area).abs() < 1e-3 * (area + 1.0), "{} against {}", total, area);
+        }
+
+        #[test]
+        fn line_coverage_ignores_direction(a in line_end(), b in line_end(), width in 0.25f32..4.0) {
+            prop_assert_eq!(line_coverage(a, b, width), line_coverage(b, a, width));
+        }
+
+        #[test]
+        fn shared_edges_have_no_gaps_or_overlaps(quad in convex_quad(), traversal in traversal()) {
+            let [a, b, c, d] = quad;
+            let split_ac = coverage_with(traversal, &[[a, b, c], [a, c, d]]);
+            let split_bd = coverage_with(traversal, &[[a, b, d], [b, c, d]]);
+
+            prop_assert!(split_ac.values().all(|&count| count == 1));
+            prop_assert!(split_bd.values().all(|&count| count == 1));
+            prop_assert_eq!(split_ac.keys().collect::<std::collections::BTreeSet<_>>(), split_bd.keys().collect());
+        }
+
+        // Cutting a triangle to the canvas and splitting it into a fan leaves the same pixels, apart from
+        // centers so close to an edge that rounding at the new corners, or their fixed point snap, moves them across
+        #[test]
+        fn clipping_keeps_the_coverage_of_large_triangles(triangle in [large_point(), large_point(), large_point()], traversal in traversal()) {
+            let parts = Rasterizer::clip_to_canvas(triangle, CANVAS);
+            let clipped = coverage_with(traversal, &parts);
+            let whole = coverage_with(traversal, &[triangle]);
+
+            prop_assert!(clipped.values().all(|&count| count == 1));
+            for &(x, y) in clipped.keys().filter(|pixel| !whole.contains_key(pixel)).chain(whole.keys().filter(|pixel| !clipped.contains_key(pixel))) {
+                let distance = distance_to_edges(triangle, [x as f32, y as f32]);
+                prop_assert!(distance < 0.01, "({}, {}) is {} from an edge", x, y, distance);
+            }
+        }
+
+        #[test]
+        fn winding_does_not_change_coverage(quad in convex_quad(), traversal in traversal()) {
+            let [a, b, c, _] = quad;
+            prop_assert_eq!(coverage_with(traversal, &[[a, b, c]]), coverage_with(traversal, &[[c, b, a]]));
+        }
+
+        // With corners on whole pixels both traversals compute exactly, so the fill rule must pick the same pixels
+        #[test]
+        fn traversals_agree_on_pixel_aligned_triangles(a in pixel_corner(), b in pixel_corner(), c in pixel_corner()) {
+            prop_assert_eq!(coverage_with(Traversal::Scanline, &[[a, b, c]]), coverage_with(Traversal::EdgeFunction, &[[a, b, c]]));
+        }
+
+        #[test]
+        fn barycentric_weights_rebuild_the_pixel(quad in convex_quad()) {
+            let [a, b, c, _] = quad;
+            let mut pixels = vec![];
+            Rasterizer::edge_function_coverage([a, b, c], CANVAS, |x, y, weights| pixels.push((x, y, weights)));
+
+            for (x, y, weights) in pixels {
+                prop_assert!(weights.iter().all(|&weight| weight >= 0.0));
+                prop_assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+                let rebuilt_x = weights[0] * a[0] + weights[1] * b[0] + weights[2] * c[0];
+                let rebuilt_y = weights[0] * a[1] + weights[1] * b[1] + weights[2] * c[1];
+                // Snapping the corners to 1/256 of a pixel moves the rebuilt position by about as much
+                prop_assert!((rebuilt_x - x as f32).abs() < 0.05 && (rebuilt_y - y as f32).abs() < 0.05);
+            }
+        }
+    }
+
+    #[test]
+    fn pixel_centers_on_edges_follow_the_top_left_rule() {
+        // Square from (0, 0) to (4, 4) split on its diagonal, the left column and top row are inside
+        let mut expected: Vec<(i32, i32)> = (1 ..= 4).flat_map(|y| (0 .. 4).map(move |x| (x, y))).collect();
+        expected.sort();
+        for traversal in [Traversal::Scanline, Traversal::EdgeFunction] {
+            let square = coverage_with(traversal, &[[[0.0, 0.0], [0.0, 4.0], [4.0, 4.0]], [[0.0, 0.0], [4.0, 4.0], [4.0, 0.0]]]);
+            let mut covered: Vec<(i32, i32)> = square.keys().copied().collect();
+            covered.sort();
+            assert_eq!(covered, expected, "{:?}", traversal);
+        }
+    }
+
+    // Wu's weighting: a line 0.25 above the pixel centers leaves 0.75 on its row and 0.25 on the one above
+    #[test]
+    fn thin_lines_split_between_neighbouring_pixels() {
+        let covered = line_coverage([-4.0, 0.25], [4.0, 0.25], 1.0);
+        for x in -3 ..= 3 {
+            assert!((covered[&(x, 0)] - 0.75).abs() < 1e-6);
+            assert!((covered[&(x, 1)] - 0.25).abs() < 1e-6);
+        }
+        // The end pixels are cut through their centers
+        assert!((covered[&(4, 0)] - 0.375).abs() < 1e-6);
+        assert_eq!(covered.len(), 18);
+    }
+
+    fn empty_rasterizer(canvas: [i32; 2]) -> Rasterizer {
+        let projection = Projection::Perspective(PerspectiveProjection::new(60.0, 1.0, 1.0, 10.0));
+        let mut rasterizer = Rasterizer::new(Camera::new(Point3::ORIGIN, None), projection);
+        rasterizer.set_canvas_size(canvas);
+        return rasterizer;
+    }
+
+    #[test]
+    fn long_lines_only_emit_points_on_the_canvas() {
+        let mut rasterizer = empty_rasterizer([40, 40]);
+        rasterizer.draw_line([-1_000_000, -30], [1_000_000, 50], Color::WHITE);
+        rasterizer.draw_line([3, -2_000_000], [3, 2_000_000], Color::WHITE);
+        rasterizer.draw_line([100, 100], [300, 50], Color::WHITE);
+        // One point per column for the flat line and one per row for the vertical one
+        assert_eq!(rasterizer.get_state().len(), 80);
+
+        // A line coming in from outside the canvas keeps the pixels the unclipped line has on it
+        let mut clipped = empty_rasterizer([40, 40]);
+        clipped.draw_line([-30, -25], [12, 17], Color::WHITE);
+        let pixels: Vec<[f32; 3]> = clipped.get_state().iter().map(|vertex| vertex.position).collect();
+        let mut expected: Vec<[f32; 3]> = (-20 ..= 12).map(|step| [step as f32 / 20.0, (step + 5) as f32 / 20.0, 0.0]).collect();
+        expected.retain(|position| position[1] < 1.0);
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn blend_pixel_mixes_with_what_is_already_drawn() {
+        let mut rasterizer = empty_rasterizer([8, 8]);
+
+        rasterizer.put_pixel(1, 1, Color::RED);
+        rasterizer.blend_pixel(1, 1, Rgba::from_color(Color::BLUE, 0.25), BlendMode::Over);
+        rasterizer.blend_pixel(2, 2, Rgba::from_color(Color::WHITE, 0.5), BlendMode::Over);
+        rasterizer.blend_pixel(3, 3, Rgba::from_color(Color::WHITE, 0.0), BlendMode::Over);
+
+        let close = |a: Color, b: Color| (a - b).to_normalized().iter().all(|channel| channel.abs() < 1e-4);
+        assert!(close(rasterizer.get_pixel(1, 1), Color::new(191.25, 0.0, 63.75)));
+        assert!(close(rasterizer.get_pixel(2, 2), BACKGROUND.lerp(Color::WHITE, 0.5)));
+        assert!(close(rasterizer.get_pixel(3, 3), BACKGROUND));
+        assert_eq!(rasterizer.get_state().len(), 3);
+    }
+
+    #[test]
+    fn blend_modes_mix_with_the_destination() {
+        let destination = Color::new(200.0, 100.0, 50.0);
+        let source = Rgba::new(100.0, 255.0, 0.0, 0.5);
+        let close = |a: Color, b: Color| (a - b).to_normalized().iter().all(|channel| channel.abs() < 1e-4);
+
+        assert!(close(BlendMode::Over.blend(destination, source), Color::new(150.0, 177.5, 25.0)));
+        assert!(close(BlendMode::Additive.blend(destination, source), Color::new(250.0, 227.5, 50.0)));
+        // Multiplying by 100/255, 1 and 0, then halfway back by the alpha
+        assert!(close(BlendMode::Multiply.blend(destination, source), Color::new(139.2157, 100.0, 25.0)));
+        for mode in [BlendMode::Over, BlendMode::Additive, BlendMode::Multiply] {
+            assert!(close(mode.blend(destination, Rgba::from_color(Color::WHITE, 0.0)), destination));
+        }
+    }
+
+    // Square facing the camera at the given depth, covering the middle of the canvas
+    fn square(depth: f32, color: Rgba) -> Node {
+        let half = 0.4 * depth;
+        let vertices = vec![
+            Point3::new(-half, -half, depth),
+            Point3::new(half, -half, depth),
+            Point3::new(half, half, depth),
+            Point3::new(-half, half, depth),
+        ];
+        let mut mesh = Mesh::new(vertices, vec![[0, 1, 2], [0, 2, 3]]).unwrap();
+        mesh.set_color(color);
+        return Node::new(Mat4::IDENTITY, Some(Shape::Mesh(Rc::new(mesh))));
+    }
+
+    fn render_squares(squares: Vec<Node>) -> Color {
+        let mut rasterizer = empty_rasterizer([40, 40]);
+        let mut root = Node::default();
+        for square in squares {
+            root.add_child(square);
+        }
+        rasterizer.set_scene(root);
+        rasterizer.pass();
+        return rasterizer.get_pixel(0, 0);
+    }
+
+    #[test]
+    fn translucent_triangles_blend_back_to_front_after_opaque_ones() {
+        let close = |a: Color, b: Color| (a - b).to_normalized().iter().all(|channel| channel.abs() < 1e-4);
+        let red = square(8.0, Rgba::opaque(Color::RED));
+        let green = || square(4.0, Rgba::from_color(Color::GREEN, 0.5));
+        let blue = || square(2.0, Rgba::from_color(Color::BLUE, 0.5));
+        let expected = Color::RED.lerp(Color::GREEN, 0.5).lerp(Color::BLUE, 0.5);
+
+        // The nearest square comes first in the scene, sorting still blends the farthest first
+        assert!(close(render_squares(vec![blue(), green(), red]), expected));
+        let red = square(8.0, Rgba::opaque(Color::RED));
+        assert!(close(render_squares(vec![red, green(), blue()]), expected));
+
+        // Translucent squares do not write depth, and opaque ones hide them no matter the order
+        let behind = square(8.0, Rgba::from_color(Color::BLUE, 0.5));
+        assert!(close(render_squares(vec![behind, square(4.0, Rgba::opaque(Color::RED))]), Color::RED));
+    }
+
+    // Where the scene definition puts a box corner: scaled, rotated, then moved
+    fn placed_corner(scale: Vec3, position: Point3, rotation: &Option<Rotation>, corner: Point3) -> Point3 {
+        let scaled = corner.to_vec3() * scale;
+        return match rotation {
+            Some(rotation) => position + rotation.rotate_vector(scaled),
+            None => position + scaled,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn box_model_matrix_places_every_corner(
+            scale in [0.1f32..3.0, 0.1f32..3.0, 0.1f32..3.0],
+            position in [-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0],
+            angles in prop::option::of([-180.0f32..180.0, -180.0f32..180.0, -180.0f32..180.0]),
+        ) {
+            let (scale, position) = (Vec3::from(scale), Point3::from(position));
+            let rotation = angles.map(|angles| Rotation::Euler(angles, EulerOrder::Xyz));
+            let cube = Box::new(scale, position, rotation);
+            let model = cube.get_model_mat4();
+            for corner in Box::VERTICES {
+                let expected = placed_corner(scale, position, &rotation, corner);
+                prop_assert!((model.transform_point(corner) - expected).length() < 1e-3);
+            }
+        }
+    }
+
+    // The rendered box covers its projected center and nothing outside the projection of its corners
+    #[test]
+    fn rendered_boxes_land_where_the_scene_puts_them() {
+        let boxes = [
+            (Vec3::new(0.5, 0.5, 0.5), Point3::new(1.0, 0.0, 8.0), None),
+            (Vec3::new(0.6, 0.4, 0.8), Point3::new(-1.2, 0.8, 7.0), Some(Rotation::Euler([20.0, 30.0, 0.0], EulerOrder::Xyz))),
+        ];
+        for (scale, position, rotation) in boxes {
+            let mut rasterizer = empty_rasterizer([40, 40]);
+            let cube = Box::new(scale, position, rotation);
+            let mut root = Node::default();
+            root.add_child(Node::new(cube.get_model_mat4(), Some(Shape::Mesh(Rc::new(Box::get_mesh())))));
+            rasterizer.set_scene(root);
+            rasterizer.pass();
+
+            let projected = Box::VERTICES.map(|corner| rasterizer.project_vertex(&placed_corner(scale, position, &rotation, corner)));
+            let min = [0, 1].map(|axis| projected.iter().map(|point| point[axis]).fold(f32::INFINITY, f32::min));
+            let max = [0, 1].map(|axis| projected.iter().map(|point| point[axis]).fold(f32::NEG_INFINITY, f32::max));
+            let center = rasterizer.project_vertex(&position);
+            assert_ne!(rasterizer.get_pixel(center[0].round() as i32, center[1].round() as i32), BACKGROUND);
+
+            for y in -20..20 {
+                for x in -20..20 {
+                    let inside = x as f32 >= min[0] - 1.0 && x as f32 <= max[0] + 1.0 && y as f32 >= min[1] - 1.0 && y as f32 <= max[1] + 1.0;
+                    assert!(inside || rasterizer.get_pixel(x, y) == BACKGROUND, "pixel {:?} is outside the box", (x, y));
+                }
+            }
+        }
+    }
+
+    // Both triangles of a ground reaching behind the camera cross the near plane, they are cut there
+    // instead of being dropped or projected mirrored above the horizon
+    #[test]
+    fn triangles_crossing_the_near_plane_are_clipped() {
+        let mut rasterizer = empty_rasterizer([40, 40]);
+        let mut root = Node::default();
+        let ground = Rc::new(primitives::plane(20.0, 20.0, 1, Color::WHITE));
+        root.add_child(Node::new(Mat4::translation(Vec3::new(0.0, -1.0, 0.0)), Some(Shape::Mesh(ground))));
+        rasterizer.set_scene(root);
+        rasterizer.pass();
+
+        assert!(rasterizer.get_triangle_count() >= 2);
+        for y in -20..20 {
+            for x in -20..20 {
+                // The ground ends 10 units ahead, a little below the horizon
+                let on_ground = y <= -6;
+                let above_horizon = y >= 0;
+                let pixel = rasterizer.get_pixel(x, y);
+                assert!(!on_ground || pixel != BACKGROUND, "pixel {:?} misses the ground", (x, y));
+                assert!(!above_horizon || pixel == BACKGROUND, "pixel {:?} is above the horizon", (x, y));
+            }
+        }
+    }
+
+    // Both nodes share one sphere, which is tessellated once for the shadow maps and every pass
+    #[test]
+    fn spheres_are_tessellated_once_per_scene() {
+        let sphere = Rc::new(Sphere { radius: 0.5, center: Point3::ORIGIN, color: Color::WHITE, specular: 10.0, reflective: 0.0, texture: None });
+        let scene = || {
+            let mut root = Node::default();
+            root.add_child(Node::new(Mat4::translation(Vec3::new(-1.0, 0.0, 6.0)), Some(Shape::Sphere(sphere.clone()))));
+            root.add_child(Node::new(Mat4::translation(Vec3::new(1.0, 0.0, 6.0)), Some(Shape::Sphere(sphere.clone()))));
+            return root;
+        };
+        let mut rasterizer = empty_rasterizer([40, 40]);
+        rasterizer.set_mode(RenderMode::Shaded);
+        rasterizer.set_shadows(Some(ShadowSettings::default()));
+        rasterizer.add_light(Light { mode: LightMode::Directional, intensity: 1.0, position: Point3::ORIGIN, direction: Vec3::new(0.0, 1.0, -1.0) });
+        rasterizer.set_scene(scene());
+
+        rasterizer.pass();
+        let mesh = rasterizer.get_sphere_mesh(&sphere);
+        rasterizer.pass();
+        assert_eq!(rasterizer.sphere_meshes.len(), 1);
+        assert!(Rc::ptr_eq(&mesh, &rasterizer.get_sphere_mesh(&sphere)));
+        assert_eq!(mesh.get_specular(), 10.0);
+
+        rasterizer.set_scene(scene());
+        assert!(rasterizer.sphere_meshes.is_empty());
+    }
+
+    // A small square one unit above the middle of the ground, lit from straight above
+    fn shadowed_ground(shadows: Option<ShadowSettings>) -> Rasterizer {
+        let mut rasterizer = empty_rasterizer([40, 40]);
+        rasterizer.set_mode(RenderMode::Shaded);
+        rasterizer.set_shadows(shadows);
+        rasterizer.add_light(Light { mode: LightMode::Ambient, intensity: 0.2, position: Point3::ORIGIN, direction: Vec3::new(0.0, 0.0, 0.0) });
+        rasterizer.add_light(Light { mode: LightMode::Directional, intensity: 0.8, position: Point3::ORIGIN, direction: Vec3::new(0.0, 1.0, 0.0) });
+
+        let mut root = Node::default();
+        let ground = Rc::new(primitives::plane(6.0, 6.0, 1, Color::WHITE));
+        let occluder = Rc::new(primitives::plane(1.0, 1.0, 1, Color::WHITE));
+        root.add_child(Node::new(Mat4::translation(Vec3::new(0.0, -1.0, 6.0)), Some(Shape::Mesh(ground))));
+        root.add_child(Node::new(Mat4::translation(Vec3::new(0.0, 0.0, 6.0)), Some(Shape::Mesh(occluder))));
+        rasterizer.set_scene(root);
+        rasterizer.pass();
+        return rasterizer;
+    }
+
+    // The camera sits level with the occluder, so it sees the ground below it and the shadow on it
+    #[test]
+    fn shadow_maps_darken_what_the_light_cannot_see() {
+        let close = |a: Color, b: Color| (a - b).to_normalized().iter().all(|channel| channel.abs() < 1e-2);
+        let [below, beside] = [Point3::new(0.0, -1.0, 6.0), Point3::new(0.0, -1.0, 4.0)];
+
+        let rasterizer = shadowed_ground(Some(ShadowSettings::default()));
+        let [below, beside] = [below, beside].map(|point| rasterizer.project_vertex(&point).map(|value| value.round() as i32));
+        assert!(close(rasterizer.get_pixel(below[0], below[1]), Color::WHITE * 0.2));
+        assert!(close(rasterizer.get_pixel(beside[0], beside[1]), Color::WHITE));
+        assert!(close(rasterizer.get_pixel(below[0] + 12, below[1]), Color::WHITE));
+
+        let unshadowed = shadowed_ground(None);
+        assert!(close(unshadowed.get_pixel(below[0], below[1]), Color::WHITE));
+    }
+
+    // Matte squares facing the camera, each covering the same middle of the canvas so they overdraw
+    fn lit_squares(render_path: RenderPath, squares: &[(f32, Color)]) -> Rasterizer {
+        let mut rasterizer = empty_rasterizer([40, 40]);
+        rasterizer.set_mode(RenderMode::Shaded);
+        rasterizer.set_render_path(render_path);
+        rasterizer.add_light(Light { mode: LightMode::Ambient, intensity: 0.3, position: Point3::ORIGIN, direction: Vec3::new(0.0, 0.0, 0.0) });
+        rasterizer.add_light(Light { mode: LightMode::Directional, intensity: 0.7, position: Point3::ORIGIN, direction: Vec3::new(0.3, 0.5, -1.0) });
+
+        let mut root = Node::default();
+        for &(depth, color) in squares {
+            let half = 0.3 * depth;
+            let vertices = vec![
+                Point3::new(-half, -half, depth),
+                Point3::new(half, -half, depth),
+                Point3::new(half, half, depth),
+                Point3::new(-half, half, depth),
+            ];
+            let mut mesh = Mesh::new(vertices, vec![[0, 2, 1], [0, 3, 2]]).unwrap();
+            mesh.set_color(color);
+            root.add_child(Node::new(Mat4::IDENTITY, Some(Shape::Mesh(Rc::new(mesh)))));
+        }
+        rasterizer.set_scene(root);
+        rasterizer.pass();
+        return rasterizer;
+    }
+
+    #[test]
+    fn deferred_shading_matches_forward_and_lights_each_pixel_once() {
+        // Farthest first, so every square is drawn over the one before it
+        let squares = [(8.0, Color::GREEN), (6.0, Color::BLUE), (4.0, Color::RED)];
+        let mut forward = lit_squares(RenderPath::Forward, &squares);
+        let mut deferred = lit_squares(RenderPath::Deferred, &squares);
+
+        for y in -20..20 {
+            for x in -20..20 {
+                let difference = (forward.get_pixel(x, y) - deferred.get_pixel(x, y)).to_normalized();
+                assert!(difference.iter().all(|channel| channel.abs() < 1e-3), "pixel {:?}", (x, y));
+            }
+        }
+        // The light reflects towards the camera near the top right, a highlight both paths must draw
+        assert!(forward.get_pixel(11, 11).r > forward.get_pixel(0, 0).r + 10.0);
+        assert_eq!(deferred.get_state().len(), deferred.get_gbuffer().iter().count());
+        assert!(forward.get_state().len() > deferred.get_gbuffer().iter().count());
+        assert_eq!(forward.get_gbuffer().iter().count(), 0);
+    }
+
+    #[test]
+    fn gbuffer_keeps_the_nearest_surface_unlit() {
+        let rasterizer = lit_squares(RenderPath::Deferred, &[(8.0, Color::GREEN), (4.0, Color::RED)]);
+        let sample = rasterizer.get_gbuffer().get_sample(0, 0).unwrap();
+        assert!((sample.position - Point3::new(0.0, 0.0, 4.0)).length() < 1e-3);
+        assert!((sample.normal - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-4);
+        assert_eq!(sample.albedo, Color::RED);
+        assert_eq!(sample.specular, 50.0);
+        assert!(rasterizer.get_pixel(0, 0) != Color::RED);
+    }
+
+    // Each texel stores its own coordinates, so a nearest sample reads back the UV it was taken at
+    fn uv_texture() -> Texture {
+        let texels = (0..16).flat_map(|y| (0..16).map(move |x| Color::new(x as f32 * 16.0, y as f32 * 16.0, 0.0))).collect();
+        let mut texture = Texture::new(16, 16, texels);
+        texture.set_sampling(Filter::Nearest, WrapMode::Clamp);
+        return texture;
+    }
+
+    // A square 16 pixels across whose top edge is four times as far away as its bottom one
+    fn draw_tilted_quad(rasterizer: &mut Rasterizer, texture: &Texture) {
+        let points = [[-8.0, -8.0], [8.0, -8.0], [8.0, 8.0], [-8.0, 8.0]];
+        let depths = [2.0, 2.0, 8.0, 8.0];
+        let uvs = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+            rasterizer.draw_textured_triangle([points[a], points[b], points[c]], [depths[a], depths[b], depths[c]], [uvs[a], uvs[b], uvs[c]], texture);
+        }
+    }
+
+    // An orthographic view keeps the UVs affine over the screen however far each corner is, the center of
+    // the quad samples the center of the texture
+    #[test]
+    fn orthographic_quads_sample_the_texture_center_at_their_center() {
+        let mut rasterizer = empty_rasterizer([40, 40]);
+        rasterizer.set_projection(Projection::Orthographic(OrthographicProjection::new(-4.0, 4.0, -4.0, 4.0, 0.1, 10.0)));
+        draw_tilted_quad(&mut rasterizer, &uv_texture());
+
+        let center = rasterizer.get_pixel(0, 0);
+        assert!((center.r - 128.0).abs() <= 16.0, "{:?}", center);
+        assert!((center.g - 128.0).abs() <= 16.0, "{:?}", center);
+    }
+
+    // Across the tilted quad an orthographic view samples the affine UV of every pixel, while perspective
+    // divides by depth and reaches the far half of the texture only near the far edge
+    #[test]
+    fn only_perspective_uvs_are_divided_by_depth() {
+        let texture = uv_texture();
+        let mut orthographic = empty_rasterizer([40, 40]);
+        orthographic.set_projection(Projection::Orthographic(OrthographicProjection::new(-4.0, 4.0, -4.0, 4.0, 0.1, 10.0)));
+        draw_tilted_quad(&mut orthographic, &texture);
+        for y in -7..7 {
+            for x in -7..7 {
+                let affine = [(x + 8) as f32 * 16.0, (y + 8) as f32 * 16.0];
+                let pixel = orthographic.get_pixel(x, y);
+                assert!((pixel.r - affine[0]).abs() <= 16.0 && (pixel.g - affine[1]).abs() <= 16.0, "pixel {:?} is {:?}", (x, y), pixel);
+            }
+        }
+
+        let mut perspective = empty_rasterizer([40, 40]);
+        draw_tilted_quad(&mut perspective, &texture);
+        // 1/z halfway up is 0.3125 and v/z 0.0625, which puts v at 0.2
+        let center = perspective.get_pixel(0, 0);
+        assert!((center.g - 48.0).abs() <= 16.0, "{:?}", center);
+    }
+
+    #[test]
+    fn triangles_without_area_cover_nothing() {
+        for traversal in [Traversal::Scanline, Traversal::EdgeFunction] {
+            assert!(coverage_with(traversal, &[[[0.0, 0.0], [5.0, 5.0], [10.0, 10.0]]]).is_empty());
+        }
+    }
+}
@@ -1,44 +1,98 @@
+use std::cell::Cell;
 use std::ops::RangeInclusive;
-use crate::utilities::{
-    dot_product,
-    vector_length,
-    vector_subtraction,
-    vector_addition,
-    scale_vector,
-    color_to_vector,
-    reverse_vector,multiply_color
-};
+use std::rc::Rc;
+use crate::utilities::multiply_color;
+use crate::math::{Vec3, Point3, Mat4, Color};
 use crate::geometry::{
     Vertex,
     Sphere,
+    Facet,
     Light,
     LightMode
 };
+use crate::texture::{Texture, Filter, WrapMode};
+use crate::scene::{Node, Shape, transform_sphere};
+use crate::frame::{Frame, BACKGROUND};
+use crate::projection::{
+    Camera,
+    Projection,
+    PerspectiveProjection,
+};
+// Cone traced along a ray to estimate the pixel footprint for texture filtering
+#[derive(Clone, Copy)]
+struct RayCone {
+    width: f32,
+    spread_angle: f32,
+}
+
+// Anything a ray can hit, spheres keep their analytic normal and facets are lit from both sides
+#[derive(Clone, Copy)]
+enum Surface<'a> {
+    Sphere(&'a Sphere),
+    Facet(&'a Facet),
+}
+
+impl Surface<'_> {
+    fn get_normal(&self, position: Point3, direction: Vec3) -> Vec3 {
+        match self {
+            Surface::Sphere(sphere) => sphere.get_normal(position),
+            Surface::Facet(facet) => {
+                let normal = facet.get_shading_normal(position);
+                if facet.get_normal().dot(direction) > 0.0 { -normal } else { normal }
+            },
+        }
+    }
+
+    fn get_color(&self, position: Point3, footprint: f32) -> Color {
+        match self {
+            Surface::Sphere(sphere) => sphere.get_color(position, footprint),
+            Surface::Facet(facet) => facet.get_color(position, footprint),
+        }
+    }
+
+    fn get_specular(&self) -> f32 {
+        match self {
+            Surface::Sphere(sphere) => sphere.specular,
+            Surface::Facet(facet) => facet.specular,
+        }
+    }
+
+    fn get_reflective(&self) -> f32 {
+        match self {
+            Surface::Sphere(sphere) => sphere.reflective,
+            Surface::Facet(facet) => facet.reflective,
+        }
+    }
+}
+
 pub struct Raytracer {
     state: Vec<Vertex>,
+    canvas: [i32; 2],
     scene: Vec<Sphere>,
+    facets: Vec<Facet>,
     lights: Vec<Light>,
+    camera: Camera,
+    projection: Projection,
+    // Primary, reflected and shadow rays cast in the last pass, counted while tracing through &self
+    ray_count: Cell<usize>,
 }
 impl Raytracer {
     pub const CANVAS: [i32; 2] = [ 1600, 1600 ];
     // Canvas size
 
-    const VIEWPORT: [f32; 3] = [1.0, 1.0, 1.0];
+    pub const VIEWPORT: [f32; 3] = [1.0, 1.0, 1.0];
     // Viewport width, height and depth which is camera distance
 
-    const CAMERA_POSITION: [f32; 3] = [0.0, 0.0, 0.0];
-    // Camera 3D position
-
-    const BACKGROUND_COLOR: [i32; 3] = [0, 0, 0];
+    const BACKGROUND_COLOR: Color = Color::BLACK;
     // Default color for scene
 
-    pub fn new() -> Self {
-        Self { state: vec![], scene: vec![], lights: vec![] }
+    pub fn new(camera: Camera, projection: Projection) -> Self {
+        Self { state: vec![], canvas: Self::CANVAS, scene: vec![], facets: vec![], lights: vec![], camera, projection, ray_count: Cell::new(0) }
     }
-    pub fn put_pixel(&mut self, x: i32, y: i32, rgb: [f32; 3]) {
-        let x_cord = x as f32 / (Self::CANVAS[0] / 2) as f32;
-        let y_cord = y as f32 / (Self::CANVAS[1] / 2) as f32;
-        let color = [ rgb[0] / 255.0, rgb[1] / 255.0, rgb[2] / 255.0 ];
+    pub fn put_pixel(&mut self, x: i32, y: i32, rgb: Color) {
+        let x_cord = x as f32 / (self.canvas[0] / 2) as f32;
+        let y_cord = y as f32 / (self.canvas[1] / 2) as f32;
+        let color = rgb.to_normalized();
 
         let result = multiply_color(color, 0.78);
 
@@ -47,36 +101,89 @@ impl Raytracer {
     pub fn get_state(&mut self) -> &[Vertex] {
         return self.state.as_slice();
     }
-    pub fn get_canvas_size(&mut self) -> [i32; 2] {
-        return Self::CANVAS;
+    pub fn get_frame(&self) -> Frame {
+        return Frame::from_vertices(&self.state, self.canvas, BACKGROUND);
+    }
+    pub fn get_canvas_size(&self) -> [i32; 2] {
+        return self.canvas;
+    }
+    pub fn set_canvas_size(&mut self, canvas: [i32; 2]) {
+        self.canvas = canvas;
+    }
+    pub fn get_camera_mut(&mut self) -> &mut Camera {
+        return &mut self.camera;
+    }
+    pub fn get_projection(&self) -> &Projection {
+        return &self.projection;
+    }
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+    pub fn get_ray_count(&self) -> usize {
+        return self.ray_count.get();
     }
 
     pub fn add_to_scene(&mut self, sphere: Sphere) {
         self.scene.push(sphere);
     }
 
+    pub fn add_scene(&mut self, root: &Node) {
+        root.traverse(Mat4::IDENTITY, &mut |shape, transform| {
+            match shape {
+                Shape::Sphere(sphere) => self.scene.push(transform_sphere(sphere, transform)),
+                Shape::Mesh(mesh) => self.facets.extend(mesh.get_facets(transform)),
+            }
+        });
+    }
+
     pub fn add_light(&mut self, light: Light) {
         self.lights.push(light);
     }
 
     fn get_canvas_range(&mut self, axis: char) -> RangeInclusive<i32> {
         match axis {
-            'x' => { ( -Self::CANVAS[0]/2 ..= Self::CANVAS[0]/2 ) },
-            'y' => { ( -Self::CANVAS[1]/2 ..= Self::CANVAS[1]/2 ) },
+            'x' => { ( -self.canvas[0]/2 ..= self.canvas[0]/2 ) },
+            'y' => { ( -self.canvas[1]/2 ..= self.canvas[1]/2 ) },
             _ => { ( -1 ..= 1 ) }
         }
     }
 
-    fn canvas_to_viewport(&mut self, x: i32, y: i32) -> [f32; 3] {
-        let x_pos = x as f32 * Self::VIEWPORT[0] / Self::CANVAS[0] as f32;
-        let y_pos = y as f32 * Self::VIEWPORT[1] / Self::CANVAS[1] as f32;
+    fn canvas_to_viewport(&mut self, x: i32, y: i32) -> Vec3 {
+        let x_pos = x as f32 * Self::VIEWPORT[0] / self.canvas[0] as f32;
+        let y_pos = y as f32 * Self::VIEWPORT[1] / self.canvas[1] as f32;
         let z_pos = Self::VIEWPORT[2];
-        return [x_pos, y_pos, z_pos];
+        return Vec3::new(x_pos, y_pos, z_pos);
+    }
+
+    // Builds the ray in camera space and moves it into the world with the camera transform
+    fn canvas_to_ray(&mut self, x: i32, y: i32, camera_mat4: Mat4) -> (Point3, Vec3) {
+        let (origin, direction) = match &self.projection {
+            Projection::Perspective(_) => (Point3::ORIGIN, self.canvas_to_viewport(x, y)),
+            Projection::Orthographic(orthographic) => {
+                // Parallel rays: every pixel starts on the view window and looks down +Z
+                (orthographic.canvas_to_window(x, y, self.canvas), Vec3::new(0.0, 0.0, 1.0))
+            },
+        };
+        return (camera_mat4.transform_point(origin), camera_mat4.transform_vector(direction));
+    }
+
+    fn get_primary_cone(&self) -> RayCone {
+        match &self.projection {
+            Projection::Perspective(_) => RayCone {
+                width: 0.0,
+                spread_angle: Self::VIEWPORT[0] / self.canvas[0] as f32 / Self::VIEWPORT[2],
+            },
+            Projection::Orthographic(orthographic) => {
+                let pixel_start = orthographic.canvas_to_window(0, 0, self.canvas);
+                let pixel_end = orthographic.canvas_to_window(1, 0, self.canvas);
+                RayCone { width: pixel_end.x - pixel_start.x, spread_angle: 0.0 }
+            },
+        }
     }
 
-    fn diffuse_reflection(&self, light_intensity: f32, light_vec: [f32; 3], normal_vec: [f32; 3]) -> f32 {
-        let light_to_surface = dot_product(normal_vec, light_vec);
-        let normalized_vectors = vector_length(normal_vec) * vector_length(light_vec);
+    fn diffuse_reflection(&self, light_intensity: f32, light_vec: Vec3, normal_vec: Vec3) -> f32 {
+        let light_to_surface = normal_vec.dot(light_vec);
+        let normalized_vectors = normal_vec.length() * light_vec.length();
 
         if light_to_surface > 0.0 {
             return light_intensity * light_to_surface / normalized_vectors;
@@ -84,16 +191,16 @@ impl Raytracer {
         return 0.0;
     }
 
-    fn reflect_ray(&self, ray: [f32; 3], normal: [f32; 3]) -> [f32; 3] {
-        let projection_scale = dot_product(normal, ray);
-        let translation = scale_vector(normal, 2.0 * projection_scale);
-        return vector_subtraction(translation, ray);
+    fn reflect_ray(&self, ray: Vec3, normal: Vec3) -> Vec3 {
+        let projection_scale = normal.dot(ray);
+        let translation = normal * (2.0 * projection_scale);
+        return translation - ray;
     }
 
-    fn specular_reflection(&self, light_intensity: f32, light_vec: [f32; 3], normal_vec: [f32; 3], bounce_vec: [f32; 3], specular_scale: f32) -> f32 {
+    fn specular_reflection(&self, light_intensity: f32, light_vec: Vec3, normal_vec: Vec3, bounce_vec: Vec3, specular_scale: f32) -> f32 {
         let reflection = self.reflect_ray(light_vec, normal_vec);
-        let reflection_offset = dot_product(reflection, bounce_vec);
-        let normalized_vectors = vector_length(reflection) * vector_length(bounce_vec);
+        let reflection_offset = reflection.dot(bounce_vec);
+        let normalized_vectors = reflection.length() * bounce_vec.length();
 
         if reflection_offset > 0.0 {
             return light_intensity * (reflection_offset / normalized_vectors).powf(specular_scale);
@@ -101,23 +208,24 @@ impl Raytracer {
         return 0.0;
     }
 
-    fn add_reflection(&self, color: [f32; 3], reflection: [f32; 3], reflective: f32) -> [f32; 3] {
-        let base_color = scale_vector(color, 1.0 - reflective);
-        let translation = scale_vector(reflection, reflective);
-        return vector_addition(base_color, translation);
+    fn add_reflection(&self, color: Color, reflection: Color, reflective: f32) -> Color {
+        let base_color = color * (1.0 - reflective);
+        let translation = reflection * reflective;
+        return base_color + translation;
     }
 
-    fn compute_lighting(&self, position: [f32; 3], normal: [f32; 3], bounce: [f32; 3], specular: f32) -> f32 {
+    fn compute_lighting(&self, position: Point3, normal: Vec3, bounce: Vec3, specular: f32) -> f32 {
         let mut light_accumulator = 0.0;
         for light in self.lights.iter() {
             match light.mode {
                 LightMode::Ambient => {
                     light_accumulator += light.intensity
                 }
-                LightMode::Point => {
-                    let light_vec = vector_subtraction(light.position, position);
-                    let (shadow_sphere, _) = self.closest_intersection(position, light_vec, (0.001 ..= 1.0));
-                    match shadow_sphere {
+                LightMode::Point | LightMode::Spot(_) => {
+                    if !light.reaches(position) { continue; }
+                    let light_vec = light.position - position;
+                    let (shadow_surface, _) = self.closest_intersection(position, light_vec, (0.001 ..= 1.0));
+                    match shadow_surface {
                         None => {
                             light_accumulator += self.diffuse_reflection(light.intensity, light_vec, normal);
                             light_accumulator += self.specular_reflection(light.intensity, light_vec, normal, bounce, specular);
@@ -126,8 +234,8 @@ impl Raytracer {
                     }
                 }
                 LightMode::Directional => {
-                    let (shadow_sphere, _) = self.closest_intersection(position, light.direction, (0.001 ..= f32::INFINITY));
-                    match shadow_sphere {
+                    let (shadow_surface, _) = self.closest_intersection(position, light.direction, (0.001 ..= f32::INFINITY));
+                    match shadow_surface {
                         None => {
                             light_accumulator += self.diffuse_reflection(light.intensity, light.direction, normal);
                             light_accumulator += self.specular_reflection(light.intensity, light.direction, normal, bounce, specular);
@@ -140,56 +248,76 @@ impl Raytracer {
         return light_accumulator;
     }
 
-    fn closest_intersection(&self, origin: [f32; 3], direction: [f32; 3], ray_range: RangeInclusive<f32>) -> (Option<&Sphere>, f32) {
+    fn closest_intersection(&self, origin: Point3, direction: Vec3, ray_range: RangeInclusive<f32>) -> (Option<Surface<'_>>, f32) {
+        self.ray_count.set(self.ray_count.get() + 1);
         let mut closest_t = f32::INFINITY;
-        let mut closest_sphere: Option<&Sphere> = None;
+        let mut closest_surface: Option<Surface<'_>> = None;
 
         for sphere in self.scene.iter() {
             let (t1, t2) = sphere.intersect_ray(origin, direction);
 
             if ray_range.contains(&t1) && t1 < closest_t {
                 closest_t = t1;
-                closest_sphere = Some(sphere);
+                closest_surface = Some(Surface::Sphere(sphere));
             }
 
             if ray_range.contains(&t2) && t2 < closest_t {
                 closest_t = t2;
-                closest_sphere = Some(sphere);
+                closest_surface = Some(Surface::Sphere(sphere));
             }
         }
 
-        return (closest_sphere, closest_t)
-    }
+        for facet in self.facets.iter() {
+            let t = facet.intersect_ray(origin, direction);
 
-    fn trace_ray(&self, origin: [f32; 3], direction: [f32; 3], t_min: f32, t_max: f32, depth: u32) -> [f32; 3] {
-        let ray_range = (t_min ..= t_max);
-        let (closest_sphere, closest_t) = self.closest_intersection(origin, direction, ray_range);
+            if ray_range.contains(&t) && t < closest_t {
+                closest_t = t;
+                closest_surface = Some(Surface::Facet(facet));
+            }
+        }
 
-        match closest_sphere {
-            Some(sphere) => {
-                let position = vector_addition(Self::CAMERA_POSITION, scale_vector(direction, closest_t));
-                let normal = sphere.get_normal(position);
-                let reversed_direction = reverse_vector(direction);
-                let light_accumulated = self.compute_lighting(position, normal, reversed_direction, sphere.specular);
-                let sphere_color = color_to_vector(sphere.color);
-                let local_color = scale_vector(sphere_color, light_accumulated);
+        return (closest_surface, closest_t)
+    }
 
-                if sphere.reflective <= 0.0 || depth <= 0 { return local_color; }
+    fn trace_ray(&self, origin: Point3, direction: Vec3, t_min: f32, t_max: f32, depth: u32, cone: RayCone) -> Color {
+        let ray_range = (t_min ..= t_max);
+        let (closest_surface, closest_t) = self.closest_intersection(origin, direction, ray_range);
+
+        match closest_surface {
+            Some(surface) => {
+                let position = origin + direction * closest_t;
+                let normal = surface.get_normal(position, direction);
+                let reversed_direction = -direction;
+                let light_accumulated = self.compute_lighting(position, normal, reversed_direction, surface.get_specular());
+                let direction_length = direction.length();
+                let cone_width = cone.width + cone.spread_angle * closest_t * direction_length;
+                let incidence = (normal.dot(direction) / direction_length).abs().max(0.1);
+                let surface_color = surface.get_color(position, cone_width / incidence);
+                let local_color = surface_color * light_accumulated;
+                let reflective = surface.get_reflective();
+
+                if reflective <= 0.0 || depth <= 0 { return local_color; }
 
                 let reflected_ray = self.reflect_ray(reversed_direction, normal);
-                let reflected_color = self.trace_ray(position, reflected_ray, 0.001, f32::INFINITY, depth - 1);
+                let reflected_cone = RayCone { width: cone_width, spread_angle: cone.spread_angle };
+                let reflected_color = self.trace_ray(position, reflected_ray, 0.001, f32::INFINITY, depth - 1, reflected_cone);
 
-                return self.add_reflection(local_color, reflected_color, sphere.reflective);
+                return self.add_reflection(local_color, reflected_color, reflective);
             },
-            None => color_to_vector(Self::BACKGROUND_COLOR),
+            None => Self::BACKGROUND_COLOR,
         }
     }
 
     pub fn pass(&mut self) {
+        self.state.clear();
+        self.ray_count.set(0);
+        let cone = self.get_primary_cone();
+        let camera_mat4 = self.camera.get_world_mat4();
         for x in self.get_canvas_range('x').clone() {
             for y in self.get_canvas_range('y').clone() {
-                let direction = self.canvas_to_viewport(x, y);
-                let color = self.trace_ray(Self::CAMERA_POSITION, direction, 1.0, f32::INFINITY, 2);
+                let (origin, direction) = self.canvas_to_ray(x, y, camera_mat4);
+                let (t_min, t_max) = self.projection.get_clipping_planes();
+                let color = self.trace_ray(origin, direction, t_min, t_max, 2, cone);
                 self.put_pixel(x, y, color);
             }
         }
@@ -197,66 +325,109 @@ impl Raytracer {
 }
 
 pub fn init_raytracer() -> Raytracer {
-    let mut raytracer = Raytracer::new();
+    return init_raytracer_with_canvas(Raytracer::CANVAS);
+}
+
+// The built-in scene at any resolution, used by the viewer and by the golden image tests
+pub fn init_raytracer_with_canvas(canvas: [i32; 2]) -> Raytracer {
+    let projection = Projection::Perspective(PerspectiveProjection::new(60.0, 1.0, Raytracer::VIEWPORT[2], f32::INFINITY));
+    let camera = Camera::new(Point3::ORIGIN, None);
+    let mut raytracer = Raytracer::new(camera, projection);
+    raytracer.set_canvas_size(canvas);
 
     raytracer.add_light(Light {
         mode: LightMode::Ambient,
         intensity: 0.1,
-        position: [0.0, 0.0, 0.0],
-        direction: [0.0, 0.0, 0.0],
+        position: Point3::ORIGIN,
+        direction: Vec3::new(0.0, 0.0, 0.0),
     });
 
     raytracer.add_light(Light {
         mode: LightMode::Point,
         intensity: 0.4,
-        position: [2.0, 1.0, 0.0],
-        direction: [0.0, 0.0, 0.0],
+        position: Point3::new(2.0, 1.0, 0.0),
+        direction: Vec3::new(0.0, 0.0, 0.0),
     });
 
     raytracer.add_light(Light {
         mode: LightMode::Directional,
         intensity: 0.2,
-        position: [0.0, 0.0, 0.0],
-        direction: [1.0, 4.0, 4.0],
+        position: Point3::ORIGIN,
+        direction: Vec3::new(1.0, 4.0, 4.0),
     });
 
-    raytracer.add_to_scene(Sphere {
+    let mut checkerboard = Texture::checkerboard(64, 8, Color::RED, Color::WHITE);
+    checkerboard.set_sampling(Filter::Trilinear, WrapMode::Repeat);
+    let checkerboard = Rc::new(checkerboard);
+
+    let mut root = Node::default();
+
+    root.add_child(Node::new(Mat4::translation(Vec3::new(0.0, -1.0, 3.0)), Some(Shape::Sphere(Rc::new(Sphere {
         radius: 1.0,
-        center: [0.0, -1.0, 3.0],
-        // color: [219, 176, 127],
-        color: [255, 0, 0],
+        center: Point3::ORIGIN,
+        // color: Color::new(219.0, 176.0, 127.0),
+        color: Color::new(255.0, 0.0, 0.0),
         specular: 600.0,
         reflective: 0.1,
-    });
+        texture: Some(checkerboard),
+    })))));
 
-    raytracer.add_to_scene(Sphere {
+    root.add_child(Node::new(Mat4::translation(Vec3::new(2.0, 0.0, 4.0)), Some(Shape::Sphere(Rc::new(Sphere {
         radius: 1.0,
-        center: [2.0, 0.0, 4.0],
-        // color: [116, 57, 59],
-        color: [0, 0, 255],
+        center: Point3::ORIGIN,
+        // color: Color::new(116.0, 57.0, 59.0),
+        color: Color::new(0.0, 0.0, 255.0),
         specular: 400.0,
         reflective: 0.2,
-    });
+        texture: None,
+    })))));
 
-    raytracer.add_to_scene(Sphere {
+    root.add_child(Node::new(Mat4::translation(Vec3::new(-2.0, 0.0, 4.0)), Some(Shape::Sphere(Rc::new(Sphere {
         radius: 1.0,
-        center: [-2.0, 0.0, 4.0],
-        // color: [122, 167, 203],
-        color: [0, 255, 0],
+        center: Point3::ORIGIN,
+        // color: Color::new(122.0, 167.0, 203.0),
+        color: Color::new(0.0, 255.0, 0.0),
         specular: 10.0,
         reflective: 0.3,
-    });
+        texture: None,
+    })))));
 
-    raytracer.add_to_scene(Sphere {
+    root.add_child(Node::new(Mat4::IDENTITY, Some(Shape::Sphere(Rc::new(Sphere {
         radius: 5000.0,
-        center: [0.0, -5001.0, 3.0],
-        // color: [57, 87, 165],
-        color: [255, 255, 0],
+        center: Point3::new(0.0, -5001.0, 3.0),
+        // color: Color::new(57.0, 87.0, 165.0),
+        color: Color::new(255.0, 255.0, 0.0),
         specular: 1000.0,
         reflective: 0.4,
-    });
+        texture: None,
+    })))));
+
+    raytracer.add_scene(&root);
 
     raytracer.pass();
 
     return raytracer;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projection::OrthographicProjection;
+
+    #[test]
+    fn primary_cone_spans_one_pixel() {
+        let mut raytracer = init_raytracer_with_canvas([100, 100]);
+
+        // A perspective cone starts as a point and is one pixel wide on the viewport plane
+        let cone = raytracer.get_primary_cone();
+        assert_eq!(cone.width, 0.0);
+        let pixel = raytracer.canvas_to_viewport(1, 0) - raytracer.canvas_to_viewport(0, 0);
+        assert!((cone.spread_angle * Raytracer::VIEWPORT[2] - pixel.length()).abs() < 1e-6);
+
+        // Parallel rays keep the width of a pixel on the view window
+        raytracer.set_projection(Projection::Orthographic(OrthographicProjection::new(-4.0, 4.0, -4.0, 4.0, 0.0, f32::INFINITY)));
+        let cone = raytracer.get_primary_cone();
+        assert!((cone.width - 0.08).abs() < 1e-6);
+        assert_eq!(cone.spread_angle, 0.0);
+    }
+}
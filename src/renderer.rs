@@ -0,0 +1,291 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::frame::{self, Frame};
+use crate::gbuffer::Channel;
+use crate::geometry::{Vertex, Light, LightMode, Sphere};
+use crate::math::{Vec3, Point3, Mat4, Color};
+use crate::projection::{Camera, PerspectiveProjection, OrthographicProjection, Projection, ProjectionMode};
+use crate::primitives;
+use crate::rasterizer::{Rasterizer, RenderMode, RenderPath, Traversal, LineMode, Box};
+use crate::raytracer::Raytracer;
+use crate::rotation::{Rotation, EulerOrder};
+use crate::scene::{Node, Shape};
+use crate::shadow::ShadowSettings;
+use crate::texture::{Texture, Filter, WrapMode};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    Rasterizer,
+    Raytracer,
+}
+
+// Both renderers hold the same scene and follow the same camera, so the viewer can swap between them
+pub struct Renderer {
+    backend: Backend,
+    rasterizer: Rasterizer,
+    raytracer: Raytracer,
+}
+
+impl Renderer {
+    pub fn new(rasterizer: Rasterizer, raytracer: Raytracer, backend: Backend) -> Self {
+        Self { backend, rasterizer, raytracer }
+    }
+
+    pub fn get_backend(&self) -> Backend {
+        return self.backend;
+    }
+
+    // The camera carries over so the new backend shows the same view
+    pub fn set_backend(&mut self, backend: Backend) {
+        let camera = *self.get_camera_mut();
+        self.backend = backend;
+        *self.get_camera_mut() = camera;
+    }
+
+    pub fn toggle_backend(&mut self) {
+        match self.backend {
+            Backend::Rasterizer => self.set_backend(Backend::Raytracer),
+            Backend::Raytracer => self.set_backend(Backend::Rasterizer),
+        }
+    }
+
+    // Render modes only change the rasterizer, the raytracer is always shaded
+    pub fn get_mode(&self) -> RenderMode {
+        return self.rasterizer.get_mode();
+    }
+
+    pub fn set_mode(&mut self, mode: RenderMode) {
+        self.rasterizer.set_mode(mode);
+    }
+
+    pub fn get_projection_mode(&self) -> ProjectionMode {
+        return self.rasterizer.get_projection().get_mode();
+    }
+
+    // Both backends switch together, so swapping backends keeps the kind of view
+    pub fn set_projection_mode(&mut self, mode: ProjectionMode) {
+        self.rasterizer.set_projection(rasterizer_projection(mode));
+        self.raytracer.set_projection(raytracer_projection(mode));
+    }
+
+    pub fn toggle_projection_mode(&mut self) {
+        match self.get_projection_mode() {
+            ProjectionMode::Perspective => self.set_projection_mode(ProjectionMode::Orthographic),
+            ProjectionMode::Orthographic => self.set_projection_mode(ProjectionMode::Perspective),
+        }
+    }
+
+    pub fn get_traversal(&self) -> Traversal {
+        return self.rasterizer.get_traversal();
+    }
+
+    pub fn toggle_traversal(&mut self) {
+        match self.rasterizer.get_traversal() {
+            Traversal::Scanline => self.rasterizer.set_traversal(Traversal::EdgeFunction),
+            Traversal::EdgeFunction => self.rasterizer.set_traversal(Traversal::Scanline),
+        }
+    }
+
+    pub fn get_render_path(&self) -> RenderPath {
+        return self.rasterizer.get_render_path();
+    }
+
+    pub fn toggle_render_path(&mut self) {
+        match self.rasterizer.get_render_path() {
+            RenderPath::Forward => self.rasterizer.set_render_path(RenderPath::Deferred),
+            RenderPath::Deferred => self.rasterizer.set_render_path(RenderPath::Forward),
+        }
+    }
+
+    pub fn get_line_mode(&self) -> LineMode {
+        return self.rasterizer.get_line_mode();
+    }
+
+    pub fn toggle_line_mode(&mut self) {
+        match self.rasterizer.get_line_mode() {
+            LineMode::Aliased => self.rasterizer.set_line_mode(LineMode::AntiAliased),
+            LineMode::AntiAliased => self.rasterizer.set_line_mode(LineMode::Aliased),
+        }
+    }
+
+    pub fn get_line_width(&self) -> f32 {
+        return self.rasterizer.get_line_width();
+    }
+
+    pub fn set_line_width(&mut self, line_width: f32) {
+        self.rasterizer.set_line_width(line_width);
+    }
+
+    pub fn get_shadows(&self) -> Option<ShadowSettings> {
+        return self.rasterizer.get_shadows();
+    }
+
+    pub fn toggle_shadows(&mut self) {
+        match self.rasterizer.get_shadows() {
+            Some(_) => self.rasterizer.set_shadows(None),
+            None => self.rasterizer.set_shadows(Some(ShadowSettings::default())),
+        }
+    }
+
+    pub fn get_camera_mut(&mut self) -> &mut Camera {
+        match self.backend {
+            Backend::Rasterizer => self.rasterizer.get_camera_mut(),
+            Backend::Raytracer => self.raytracer.get_camera_mut(),
+        }
+    }
+
+    pub fn pass(&mut self) {
+        match self.backend {
+            Backend::Rasterizer => self.rasterizer.pass(),
+            Backend::Raytracer => self.raytracer.pass(),
+        }
+    }
+
+    // Work done by the last rasterizer and raytracer passes
+    pub fn get_triangle_count(&self) -> usize {
+        return self.rasterizer.get_triangle_count();
+    }
+
+    pub fn get_ray_count(&self) -> usize {
+        return self.raytracer.get_ray_count();
+    }
+
+    pub fn get_state(&mut self) -> &[Vertex] {
+        match self.backend {
+            Backend::Rasterizer => self.rasterizer.get_state(),
+            Backend::Raytracer => self.raytracer.get_state(),
+        }
+    }
+
+    // Image of the last pass as it shows in the window
+    pub fn get_frame(&self) -> Frame {
+        match self.backend {
+            Backend::Rasterizer => self.rasterizer.get_frame(),
+            Backend::Raytracer => self.raytracer.get_frame(),
+        }
+    }
+
+    pub fn save_frame(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        return self.get_frame().save_png(path);
+    }
+
+    // One image per G-buffer channel inside the directory, from the last deferred rasterizer pass
+    pub fn save_gbuffer(&self, directory: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+        let gbuffer = self.rasterizer.get_gbuffer();
+        if gbuffer.iter().next().is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the last pass left nothing in the G-buffer, only deferred shaded passes write it"));
+        }
+
+        let mut paths = vec![];
+        for channel in Channel::ALL {
+            let path = frame::timestamped_path(directory.as_ref(), &format!("gbuffer-{}", channel.get_name()));
+            gbuffer.save_png(channel, &path)?;
+            paths.push(path);
+        }
+        return Ok(paths);
+    }
+}
+
+// The orthographic view is 8 units across, enough for the whole scene in front of the default camera
+fn rasterizer_projection(mode: ProjectionMode) -> Projection {
+    return match mode {
+        ProjectionMode::Perspective => Projection::Perspective(PerspectiveProjection::new(60.0, 1.0, 10.0, 70.0)),
+        ProjectionMode::Orthographic => Projection::Orthographic(OrthographicProjection::new(-4.0, 4.0, -4.0, 4.0, 0.1, 70.0)),
+    }
+}
+
+fn raytracer_projection(mode: ProjectionMode) -> Projection {
+    return match mode {
+        ProjectionMode::Perspective => Projection::Perspective(PerspectiveProjection::new(60.0, 1.0, Raytracer::VIEWPORT[2], f32::INFINITY)),
+        ProjectionMode::Orthographic => Projection::Orthographic(OrthographicProjection::new(-4.0, 4.0, -4.0, 4.0, 0.0, f32::INFINITY)),
+    }
+}
+
+fn init_scene() -> (Node, Vec<Light>) {
+    let lights = vec![
+        Light {
+            mode: LightMode::Ambient,
+            intensity: 0.2,
+            position: Point3::ORIGIN,
+            direction: Vec3::new(0.0, 0.0, 0.0),
+        },
+        Light {
+            mode: LightMode::Spot(55.0),
+            intensity: 0.6,
+            position: Point3::new(2.0, 5.0, 3.0),
+            direction: Vec3::new(-2.0, -6.0, 6.0),
+        },
+        Light {
+            mode: LightMode::Directional,
+            intensity: 0.2,
+            position: Point3::ORIGIN,
+            direction: Vec3::new(-1.0, 4.0, -4.0),
+        },
+    ];
+
+    let mut checkerboard = Texture::checkerboard(64, 4, Color::WHITE, Color::new(40.0, 40.0, 40.0));
+    checkerboard.set_sampling(Filter::Trilinear, WrapMode::Repeat);
+
+    let mut cube = Box::get_mesh();
+    cube.set_texture(Rc::new(checkerboard));
+    let cube = Rc::new(cube);
+
+    let ground = Rc::new(primitives::plane(12.0, 12.0, 1, Color::new(120.0, 120.0, 120.0)));
+
+    // The sphere stays analytic for the raytracer, the other shapes are meshes in both renderers
+    let sphere = Rc::new(Sphere {
+        radius: 0.6,
+        center: Point3::ORIGIN,
+        color: Color::new(230.0, 120.0, 40.0),
+        specular: 200.0,
+        reflective: 0.2,
+        texture: None,
+    });
+    let torus = Rc::new(primitives::torus(0.45, 0.16, 32, 16, Color::new(200.0, 60.0, 90.0)));
+    let cylinder = Rc::new(primitives::cylinder(0.45, 1.4, 24, Color::new(60.0, 160.0, 200.0)));
+    let cone = Rc::new(primitives::cone(0.5, 1.3, 24, Color::new(230.0, 200.0, 60.0)));
+    let icosphere = Rc::new(primitives::icosphere(0.6, 2, Color::new(90.0, 190.0, 110.0)));
+    let place = |x: f32, y: f32, z: f32| Mat4::translation(Vec3::new(x, y, z));
+
+    let box_a = Box::new(Vec3::new(1.0, 1.0, 1.0), Point3::new(-1.5, 0.0, 7.0), None);
+    let box_b = Box::new(Vec3::new(1.0, 1.0, 1.0), Point3::new(1.25, 2.0, 7.5), Some(Rotation::Euler([20.0, 30.0, 0.0], EulerOrder::Xyz)));
+
+    let mut root = Node::default();
+    root.add_child(Node::new(place(0.0, -1.0, 9.0), Some(Shape::Mesh(ground))));
+    root.add_child(Node::new(box_a.get_model_mat4(), Some(Shape::Mesh(cube.clone()))));
+    root.add_child(Node::new(box_b.get_model_mat4(), Some(Shape::Mesh(cube))));
+    root.add_child(Node::new(place(1.5, -0.4, 6.0), Some(Shape::Sphere(sphere))));
+    root.add_child(Node::new(place(0.2, -0.62, 5.0) * Rotation::Euler([-35.0, 0.0, 0.0], EulerOrder::Xyz).to_mat4(), Some(Shape::Mesh(torus))));
+    root.add_child(Node::new(place(4.2, -0.3, 10.0), Some(Shape::Mesh(cylinder))));
+    root.add_child(Node::new(place(-0.3, -0.35, 12.0), Some(Shape::Mesh(cone))));
+    root.add_child(Node::new(place(1.6, -0.4, 13.0), Some(Shape::Mesh(icosphere))));
+
+    return (root, lights);
+}
+
+pub fn init_renderer() -> Renderer {
+    let (root, lights) = init_scene();
+    // Slightly above the objects and aimed at the middle of the group
+    let camera = Camera::look_at(Point3::new(0.0, 1.0, 0.0), Point3::new(0.8, -0.5, 8.0), Vec3::new(0.0, 1.0, 0.0));
+
+    let projection_mode = ProjectionMode::Perspective;
+
+    let mut rasterizer = Rasterizer::new(camera, rasterizer_projection(projection_mode));
+    rasterizer.set_mode(RenderMode::Shaded);
+    rasterizer.set_shadows(Some(ShadowSettings::default()));
+
+    let mut raytracer = Raytracer::new(camera, raytracer_projection(projection_mode));
+
+    for light in lights {
+        rasterizer.add_light(light.clone());
+        raytracer.add_light(light);
+    }
+    raytracer.add_scene(&root);
+    rasterizer.set_scene(root);
+
+    let mut renderer = Renderer::new(rasterizer, raytracer, Backend::Rasterizer);
+    renderer.pass();
+
+    return renderer;
+}
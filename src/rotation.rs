@@ -0,0 +1,322 @@
+use crate::math::{Vec3, Mat4};
+use crate::utilities::{Axis, axis_direction, to_inverse_rotation_mat4};
+
+// Order in which the Euler angles are applied, Xyz rotates around X first and Z last
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EulerOrder { Xyz, Xzy, Yxz, Yzx, Zxy, Zyx }
+
+impl EulerOrder {
+    fn get_axes(&self) -> [Axis; 3] {
+        match self {
+            EulerOrder::Xyz => [Axis::X, Axis::Y, Axis::Z],
+            EulerOrder::Xzy => [Axis::X, Axis::Z, Axis::Y],
+            EulerOrder::Yxz => [Axis::Y, Axis::X, Axis::Z],
+            EulerOrder::Yzx => [Axis::Y, Axis::Z, Axis::X],
+            EulerOrder::Zxy => [Axis::Z, Axis::X, Axis::Y],
+            EulerOrder::Zyx => [Axis::Z, Axis::Y, Axis::X],
+        }
+    }
+}
+
+fn get_axis_angle(angles: [f32; 3], axis: Axis) -> f32 {
+    match axis {
+        Axis::X => angles[0],
+        Axis::Y => angles[1],
+        Axis::Z => angles[2],
+    }
+}
+
+fn get_axis_vector(axis: Axis) -> Vec3 {
+    Vec3::from(axis_direction(axis))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self { w: 1.0, x: 0.0, y: 0.0, z: 0.0 }
+    }
+}
+
+impl Quaternion {
+    pub fn new(w: f32, x: f32, y: f32, z: f32) -> Self {
+        Self { w, x, y, z }
+    }
+
+    // Angle in degrees around an axis of any length
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let length = axis.length();
+        if length == 0.0 { return Self::default(); }
+
+        let half_radian = angle.to_radians() / 2.0;
+        let scaled_axis = axis * (half_radian.sin() / length);
+        Self::new(half_radian.cos(), scaled_axis.x, scaled_axis.y, scaled_axis.z)
+    }
+
+    // Angles in degrees around X, Y and Z, applied in the given order
+    pub fn from_euler(angles: [f32; 3], order: EulerOrder) -> Self {
+        order.get_axes()
+            .iter()
+            .fold(Self::default(), |rotation, &axis| {
+                let step = Self::from_axis_angle(get_axis_vector(axis), get_axis_angle(angles, axis));
+                step.multiply(rotation)
+            })
+    }
+
+    // Hamilton product, the result applies `other` first and `self` second
+    pub fn multiply(&self, other: Quaternion) -> Self {
+        Self::new(
+            self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        )
+    }
+
+    // Rotation part of a matrix whose upper 3x3 is orthonormal, picks the largest diagonal term for stability
+    pub fn from_mat4(matrix: Mat4) -> Self {
+        let m = matrix.0;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        let quaternion = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Self::new(0.25 * s, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            Self::new((m[2][1] - m[1][2]) / s, 0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            Self::new((m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s)
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            Self::new((m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s)
+        };
+
+        quaternion.normalize()
+    }
+
+    pub fn dot(&self, other: Quaternion) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let length = self.length();
+        if length == 0.0 { return Self::default(); }
+        Self::new(self.w / length, self.x / length, self.y / length, self.z / length)
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    // Spherical interpolation along the shortest arc, t in 0..=1
+    pub fn slerp(&self, other: Quaternion, t: f32) -> Self {
+        let mut end = other;
+        let mut cosine = self.dot(other);
+        if cosine < 0.0 {
+            end = Self::new(-other.w, -other.x, -other.y, -other.z);
+            cosine = -cosine;
+        }
+
+        // Nearly parallel quaternions fall back to a normalized lerp to avoid dividing by sin(0)
+        let (start_weight, end_weight) = if cosine > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cosine.acos();
+            let sine = angle.sin();
+            (((1.0 - t) * angle).sin() / sine, (t * angle).sin() / sine)
+        };
+
+        Self::new(
+            self.w * start_weight + end.w * end_weight,
+            self.x * start_weight + end.x * end_weight,
+            self.y * start_weight + end.y * end_weight,
+            self.z * start_weight + end.z * end_weight,
+        ).normalize()
+    }
+
+    pub fn rotate_vector(&self, vector: Vec3) -> Vec3 {
+        let pure = Self::new(0.0, vector.x, vector.y, vector.z);
+        let rotated = self.multiply(pure).multiply(self.conjugate());
+        Vec3::new(rotated.x, rotated.y, rotated.z)
+    }
+
+    pub fn to_mat4(self) -> Mat4 {
+        let Quaternion { w, x, y, z } = self.normalize();
+
+        Mat4([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z),       2.0 * (x * z + w * y),       0.0],
+            [2.0 * (x * y + w * z),       1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x),       0.0],
+            [2.0 * (x * z - w * y),       2.0 * (y * z + w * x),       1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0,                         0.0,                         0.0,                         1.0],
+        ])
+    }
+}
+
+// Orientation of an object or camera, angles are in degrees like the rest of the scene description
+#[derive(Clone, Copy)]
+pub enum Rotation {
+    Euler([f32; 3], EulerOrder),
+    AxisAngle(Vec3, f32),
+    Quaternion(Quaternion),
+}
+
+impl Rotation {
+    pub fn to_quaternion(self) -> Quaternion {
+        match self {
+            Rotation::Euler(angles, order) => Quaternion::from_euler(angles, order),
+            Rotation::AxisAngle(axis, angle) => Quaternion::from_axis_angle(axis, angle),
+            Rotation::Quaternion(quaternion) => quaternion.normalize(),
+        }
+    }
+
+    pub fn rotate_vector(&self, vector: Vec3) -> Vec3 {
+        match self {
+            Rotation::AxisAngle(axis, angle) => vector.rotate_around(*axis, *angle),
+            _ => self.to_quaternion().rotate_vector(vector),
+        }
+    }
+
+    pub fn to_mat4(self) -> Mat4 {
+        match self {
+            Rotation::Euler(angles, order) => {
+                order.get_axes().iter().fold(Mat4::IDENTITY, |matrix, &axis| {
+                    Mat4::rotation(axis, get_axis_angle(angles, axis).to_radians()) * matrix
+                })
+            },
+            Rotation::AxisAngle(axis, angle) => Mat4::axis_rotation(axis, angle.to_radians()),
+            Rotation::Quaternion(_) => self.to_quaternion().to_mat4(),
+        }
+    }
+
+    pub fn to_inverse_mat4(self) -> Mat4 {
+        match self {
+            Rotation::Euler(angles, order) => {
+                order.get_axes().iter().fold(Mat4::IDENTITY, |matrix, &axis| {
+                    matrix * Mat4::from(to_inverse_rotation_mat4(axis, get_axis_angle(angles, axis).to_radians()))
+                })
+            },
+            Rotation::AxisAngle(axis, angle) => Mat4::axis_rotation(axis, -angle.to_radians()),
+            Rotation::Quaternion(_) => self.to_quaternion().conjugate().to_mat4(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const EPSILON: f32 = 1e-4;
+    const ORDERS: [EulerOrder; 6] = [EulerOrder::Xyz, EulerOrder::Xzy, EulerOrder::Yxz, EulerOrder::Yzx, EulerOrder::Zxy, EulerOrder::Zyx];
+
+    fn vectors_close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < EPSILON
+    }
+
+    fn mat4s_close(a: Mat4, b: Mat4) -> bool {
+        a.0.iter().flatten().zip(b.0.iter().flatten()).all(|(a, b)| (a - b).abs() < EPSILON)
+    }
+
+    // q and -q are the same rotation
+    fn quaternions_equivalent(a: Quaternion, b: Quaternion) -> bool {
+        (a.dot(b).abs() - 1.0).abs() < EPSILON
+    }
+
+    fn angles() -> impl Strategy<Value = [f32; 3]> {
+        [-180.0f32..180.0, -180.0f32..180.0, -180.0f32..180.0]
+    }
+
+    #[test]
+    fn multiply_applies_the_right_operand_first() {
+        let x_quarter = Quaternion::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), 90.0);
+        let z_quarter = Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0);
+
+        // Y goes to Z around X, then Z stays put around Z
+        let combined = z_quarter.multiply(x_quarter);
+        assert!(vectors_close(combined.rotate_vector(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 0.0, 1.0)));
+        assert!(vectors_close(x_quarter.multiply(z_quarter).rotate_vector(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(-1.0, 0.0, 0.0)));
+
+        // i * j = k
+        let k = Quaternion::new(0.0, 1.0, 0.0, 0.0).multiply(Quaternion::new(0.0, 0.0, 1.0, 0.0));
+        assert_eq!(k, Quaternion::new(0.0, 0.0, 0.0, 1.0));
+        assert_eq!(combined.multiply(Quaternion::default()), combined);
+    }
+
+    #[test]
+    fn slerp_moves_at_constant_speed_along_the_shortest_arc() {
+        let start = Quaternion::default();
+        let end = Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 90.0);
+
+        assert!(quaternions_equivalent(start.slerp(end, 0.0), start));
+        assert!(quaternions_equivalent(start.slerp(end, 1.0), end));
+        assert!(quaternions_equivalent(start.slerp(end, 0.5), Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 45.0)));
+        assert!(quaternions_equivalent(start.slerp(end, 0.25), Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 22.5)));
+
+        // The negated end is the same orientation, so the path must not swing the long way round
+        let negated = Quaternion::new(-end.w, -end.x, -end.y, -end.z);
+        assert!(quaternions_equivalent(start.slerp(negated, 0.5), start.slerp(end, 0.5)));
+
+        // Nearly identical inputs take the lerp path and stay normalized
+        let close = Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.01);
+        assert!((start.slerp(close, 0.5).length() - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn from_mat4_handles_half_turns() {
+        // A half turn has a trace of -1, so these go through the diagonal branches
+        for axis in [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 1.0, 0.0)] {
+            let quaternion = Quaternion::from_axis_angle(axis, 180.0);
+            assert!(quaternions_equivalent(Quaternion::from_mat4(quaternion.to_mat4()), quaternion), "{:?}", axis);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn from_mat4_round_trips(angles in angles(), order in proptest::sample::select(ORDERS.to_vec())) {
+            let quaternion = Quaternion::from_euler(angles, order);
+            let recovered = Quaternion::from_mat4(quaternion.to_mat4());
+            prop_assert!(quaternions_equivalent(recovered, quaternion));
+            prop_assert!(mat4s_close(recovered.to_mat4(), quaternion.to_mat4()));
+        }
+
+        #[test]
+        fn euler_representations_agree(angles in angles(), order in proptest::sample::select(ORDERS.to_vec())) {
+            let rotation = Rotation::Euler(angles, order);
+            let matrix = rotation.to_mat4();
+            prop_assert!(mat4s_close(matrix, rotation.to_quaternion().to_mat4()));
+            prop_assert!(mat4s_close(matrix * rotation.to_inverse_mat4(), Mat4::IDENTITY));
+
+            // Applying the order's axes one at a time gives the same rotation
+            let stepwise = order.get_axes().iter().fold(Mat4::IDENTITY, |matrix, &axis| {
+                Rotation::AxisAngle(get_axis_vector(axis), get_axis_angle(angles, axis)).to_mat4() * matrix
+            });
+            prop_assert!(mat4s_close(matrix, stepwise));
+        }
+    }
+
+    #[test]
+    fn euler_orders_differ_only_when_axes_do_not_commute() {
+        let single_axis = [0.0, 40.0, 0.0];
+        let reference = Rotation::Euler(single_axis, EulerOrder::Xyz).to_mat4();
+        assert!(ORDERS.iter().all(|&order| mat4s_close(Rotation::Euler(single_axis, order).to_mat4(), reference)));
+
+        // Xyz rotates around X first, which is Zyx run backwards on the inverse
+        let angles = [30.0, 45.0, 60.0];
+        let xyz = Rotation::Euler(angles, EulerOrder::Xyz);
+        let zyx = Rotation::Euler(angles, EulerOrder::Zyx);
+        assert!(!mat4s_close(xyz.to_mat4(), zyx.to_mat4()));
+        let negated = Rotation::Euler(angles.map(|angle| -angle), EulerOrder::Zyx);
+        assert!(mat4s_close(xyz.to_inverse_mat4(), negated.to_mat4()));
+    }
+}
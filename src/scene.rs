@@ -0,0 +1,134 @@
+use std::rc::Rc;
+use crate::geometry::Sphere;
+use crate::mesh::Mesh;
+use crate::math::{Vec3, Mat4};
+
+// Shapes are reference counted so a single mesh or sphere can be instanced by many nodes
+#[derive(Clone)]
+pub enum Shape {
+    Mesh(Rc<Mesh>),
+    Sphere(Rc<Sphere>),
+}
+
+#[derive(Clone)]
+pub struct Node {
+    transform: Mat4,
+    shape: Option<Shape>,
+    children: Vec<Node>,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self { transform: Mat4::IDENTITY, shape: None, children: vec![] }
+    }
+}
+
+impl Node {
+    pub fn new(transform: Mat4, shape: Option<Shape>) -> Self {
+        Self { transform, shape, children: vec![] }
+    }
+
+    pub fn add_child(&mut self, child: Node) -> &mut Node {
+        self.children.push(child);
+        return self.children.last_mut().unwrap();
+    }
+
+    pub fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    // Visits every shape in the subtree with its world transform, parents applied before children
+    pub fn traverse(&self, parent_transform: Mat4, visit: &mut dyn FnMut(&Shape, Mat4)) {
+        let world_transform = parent_transform * self.transform;
+
+        if let Some(shape) = &self.shape {
+            visit(shape, world_transform);
+        }
+        for child in self.children.iter() {
+            child.traverse(world_transform, visit);
+        }
+    }
+}
+
+// Places a local-space sphere in the world; the radius follows the largest axis scale
+pub fn transform_sphere(sphere: &Sphere, transform: Mat4) -> Sphere {
+    let scale = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)]
+        .iter()
+        .map(|&axis| transform.transform_vector(axis).length())
+        .fold(0.0, f32::max);
+
+    return Sphere {
+        radius: sphere.radius * scale,
+        center: transform.transform_point(sphere.center),
+        ..sphere.clone()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Color, Point3};
+    use crate::utilities::Axis;
+
+    fn assert_near(actual: Point3, expected: Point3) {
+        assert!((actual - expected).length() < 1e-5, "{:?} is not {:?}", actual, expected);
+    }
+
+    fn triangle() -> Rc<Mesh> {
+        let vertices = vec![Point3::ORIGIN, Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+        return Rc::new(Mesh::new(vertices, vec![[0, 1, 2]]).unwrap());
+    }
+
+    // The child is placed in its parent's space: rotated by the parent after its own offset
+    #[test]
+    fn children_compose_their_transform_after_the_parent() {
+        let parent_transform = Mat4::translation(Vec3::new(0.0, 0.0, 5.0)) * Mat4::rotation(Axis::Y, std::f32::consts::FRAC_PI_2);
+        let child_transform = Mat4::translation(Vec3::new(2.0, 0.0, 0.0));
+        let mut root = Node::new(parent_transform, None);
+        root.add_child(Node::new(child_transform, Some(Shape::Mesh(triangle()))));
+
+        let mut transforms = vec![];
+        root.traverse(Mat4::IDENTITY, &mut |_, transform| transforms.push(transform));
+        assert_eq!(transforms, vec![parent_transform * child_transform]);
+
+        let origin = transforms[0].transform_point(Point3::ORIGIN);
+        assert_near(origin, parent_transform.transform_point(Point3::new(2.0, 0.0, 0.0)));
+        assert_ne!(origin, Point3::new(2.0, 0.0, 5.0));
+    }
+
+    // A mesh shared by several nodes is visited once per node, parents before their children and
+    // siblings in the order they were added
+    #[test]
+    fn shared_meshes_are_visited_once_per_node_in_depth_first_order() {
+        let mesh = triangle();
+        let at = |x: f32| Mat4::translation(Vec3::new(x, 0.0, 0.0));
+        let mut root = Node::new(at(1.0), Some(Shape::Mesh(mesh.clone())));
+        let first = root.add_child(Node::new(at(10.0), Some(Shape::Mesh(mesh.clone()))));
+        first.add_child(Node::new(at(100.0), Some(Shape::Mesh(mesh.clone()))));
+        root.add_child(Node::new(at(1000.0), None))
+            .add_child(Node::new(at(10000.0), Some(Shape::Mesh(mesh.clone()))));
+
+        let mut visits = vec![];
+        root.traverse(Mat4::IDENTITY, &mut |shape, transform| {
+            let Shape::Mesh(visited) = shape else { panic!("only meshes are in the scene"); };
+            assert!(Rc::ptr_eq(visited, &mesh));
+            visits.push(transform.transform_point(Point3::ORIGIN).x);
+        });
+        assert_eq!(visits, vec![1.0, 11.0, 111.0, 11001.0]);
+        // The nodes hold the only other references, nothing was copied out of the Rc
+        assert_eq!(Rc::strong_count(&mesh), 5);
+    }
+
+    #[test]
+    fn sphere_radius_follows_the_largest_axis_scale() {
+        let sphere = Sphere { radius: 0.5, center: Point3::new(1.0, 1.0, 1.0), color: Color::WHITE, specular: 10.0, reflective: 0.0, texture: None };
+        let transform = Mat4::translation(Vec3::new(0.0, 2.0, 0.0)) * Mat4::scale(Vec3::new(1.0, 3.0, 0.5));
+        let placed = transform_sphere(&sphere, transform);
+        assert!((placed.radius - 1.5).abs() < 1e-6);
+        assert_near(placed.center, Point3::new(1.0, 5.0, 0.5));
+
+        // A rotation does not change which scale is largest
+        let rotated = transform_sphere(&sphere, Mat4::rotation(Axis::Z, 0.7) * Mat4::scale(Vec3::new(0.2, 4.0, 1.0)));
+        assert!((rotated.radius - 2.0).abs() < 1e-5);
+    }
+}
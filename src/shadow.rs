@@ -0,0 +1,233 @@
+use crate::geometry::{Light, LightMode};
+use crate::math::{Vec3, Point3, Mat4};
+use crate::projection::{Camera, PerspectiveProjection, OrthographicProjection, Projection};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    // Texels along each side of a shadow map
+    pub resolution: i32,
+    // Distance a surface may sit behind the stored depth and still be lit, plus the extra allowed at grazing
+    // angles where one texel covers a long stretch of depth. Too little bias shadows surfaces with stripes
+    // of their own shadow, too much lets shadows start away from the objects casting them
+    pub depth_bias: f32,
+    pub slope_bias: f32,
+    // Texels averaged on each side of a lookup for soft edges, 0 gives hard edges
+    pub filter_radius: i32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self { resolution: 1024, depth_bias: 0.02, slope_bias: 0.03, filter_radius: 1 }
+    }
+}
+
+// Depth of the nearest surfaces seen from a light, rendered with the same cameras and projections as the
+// rasterizer: directional lights look at the whole scene with an orthographic projection, spot lights look
+// down their cone with a perspective one. Depths are camera-space z from the light
+pub struct ShadowMap {
+    view: Mat4,
+    projection: Projection,
+    size: [i32; 2],
+    depths: Vec<f32>,
+    settings: ShadowSettings,
+}
+
+impl ShadowMap {
+    const SPOT_NEAR: f32 = 0.05;
+
+    // The bounds are the world-space box around everything that casts or receives shadows. Only directional
+    // and spot lights cast shadow maps, point lights would need one in every direction
+    pub fn new(light: &Light, bounds: [Point3; 2], settings: ShadowSettings) -> Option<Self> {
+        let [min, max] = bounds;
+        let center = min + (max - min) * 0.5;
+        let radius = ((max - min).length() / 2.0).max(1e-3);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        let (mut camera, projection) = match light.mode {
+            LightMode::Directional => {
+                if light.direction.length() == 0.0 { return None; }
+                let eye = center + light.direction.normalize() * (2.0 * radius);
+                let orthographic = OrthographicProjection::new(-radius, radius, -radius, radius, 0.0, 4.0 * radius);
+                (Camera::look_at(eye, center, up), Projection::Orthographic(orthographic))
+            },
+            LightMode::Spot(angle) => {
+                if light.direction.length() == 0.0 { return None; }
+                let far = (center - light.position).length() + radius;
+                let perspective = PerspectiveProjection::new(2.0 * angle.min(89.0), 1.0, Self::SPOT_NEAR, far);
+                (Camera::look_at(light.position, light.position + light.direction, up), Projection::Perspective(perspective))
+            },
+            LightMode::Ambient | LightMode::Point => return None,
+        };
+
+        let resolution = settings.resolution.max(1);
+        let size = [resolution, resolution];
+        return Some(Self {
+            view: camera.get_projection_mat4(),
+            projection,
+            size,
+            depths: vec![f32::INFINITY; (size[0] * size[1]) as usize],
+            settings,
+        });
+    }
+
+    pub fn get_size(&self) -> [i32; 2] {
+        return self.size;
+    }
+
+    // Perspective maps interpolate 1/z across a triangle, orthographic ones z itself
+    pub fn is_perspective(&self) -> bool {
+        return matches!(self.projection, Projection::Perspective(_));
+    }
+
+    // Texel position with sub-texel precision and the depth from the light, None behind the near plane
+    pub fn project(&self, position: Point3) -> Option<([f32; 2], f32)> {
+        let light_space = self.view.transform_point(position);
+        let (near, _) = self.projection.get_clipping_planes();
+        if light_space.z < near || (self.is_perspective() && light_space.z <= 0.0) {
+            return None;
+        }
+        return Some((self.projection.window_to_canvas(light_space, self.size), light_space.z));
+    }
+
+    fn texel_index(&self, x: i32, y: i32) -> Option<usize> {
+        let x_idx = x + self.size[0] / 2;
+        let y_idx = y + self.size[1] / 2;
+        if x_idx < 0 || x_idx >= self.size[0] || y_idx < 0 || y_idx >= self.size[1] {
+            return None;
+        }
+        return Some((y_idx * self.size[0] + x_idx) as usize);
+    }
+
+    // Infinitely far where nothing was drawn and outside the map, so those texels never shadow
+    pub fn get_depth(&self, x: i32, y: i32) -> f32 {
+        return match self.texel_index(x, y) {
+            Some(idx) => self.depths[idx],
+            None => f32::INFINITY,
+        }
+    }
+
+    // Keeps the nearest depth written to a texel
+    pub fn put_depth(&mut self, x: i32, y: i32, depth: f32) {
+        if let Some(idx) = self.texel_index(x, y) {
+            self.depths[idx] = self.depths[idx].min(depth);
+        }
+    }
+
+    // Share of the light reaching a surface point, from 0 in full shadow to 1 fully lit. Percentage-closer
+    // filtering compares the point against every texel around it and averages the results, which softens
+    // the edges instead of blurring the depths. `to_light` points from the surface towards the light
+    pub fn get_visibility(&self, position: Point3, normal: Vec3, to_light: Vec3) -> f32 {
+        let Some((point, depth)) = self.project(position) else { return 1.0; };
+
+        // The bias grows with the tangent of the angle to the light, capped for surfaces seen edge on
+        let cosine = normal.normalize().dot(to_light.normalize()).clamp(0.0, 1.0);
+        let tangent = ((1.0 - cosine * cosine).sqrt() / cosine.max(0.1)).min(10.0);
+        let bias = self.settings.depth_bias + self.settings.slope_bias * tangent;
+
+        let [x, y] = point.map(|value| value.round() as i32);
+        let radius = self.settings.filter_radius.max(0);
+        let mut lit = 0;
+        for dy in -radius ..= radius {
+            for dx in -radius ..= radius {
+                if depth - bias <= self.get_depth(x + dx, y + dy) {
+                    lit += 1;
+                }
+            }
+        }
+        let samples = (2 * radius + 1) * (2 * radius + 1);
+        return lit as f32 / samples as f32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // Light straight above a 4x4x4 box of scene around the origin
+    fn overhead_map(settings: ShadowSettings) -> ShadowMap {
+        let light = Light {
+            mode: LightMode::Directional,
+            intensity: 1.0,
+            position: Point3::ORIGIN,
+            direction: Vec3::new(0.0, 1.0, 0.0),
+        };
+        let bounds = [Point3::new(-2.0, -2.0, -2.0), Point3::new(2.0, 2.0, 2.0)];
+        return ShadowMap::new(&light, bounds, settings).unwrap();
+    }
+
+    // Occluder at height 1 over every texel with x below zero
+    fn half_covered(settings: ShadowSettings) -> ShadowMap {
+        let mut map = overhead_map(settings);
+        let occluder = map.project(Point3::new(0.0, 1.0, 0.0)).unwrap().1;
+        let [width, height] = map.get_size();
+        for y in -height / 2 .. height / 2 {
+            for x in -width / 2 .. 0 {
+                map.put_depth(x, y, occluder);
+            }
+        }
+        return map;
+    }
+
+    proptest! {
+        // Looking down on the scene, the light sees every ground point at the same depth below its own spot
+        #[test]
+        fn directional_maps_look_along_the_light(x in -2.0f32..2.0, z in -2.0f32..2.0, height in -2.0f32..2.0) {
+            let map = overhead_map(ShadowSettings::default());
+            let (point, depth) = map.project(Point3::new(x, height, z)).unwrap();
+            let (origin_point, origin_depth) = map.project(Point3::new(0.0, 0.0, 0.0)).unwrap();
+            prop_assert!((depth - (origin_depth - height)).abs() < 1e-3);
+            // The map spans the sphere around the bounds, 2 * sqrt(12) units across
+            let texel = 4.0 * 3.0f32.sqrt() / ShadowSettings::default().resolution as f32;
+            let across = ((point[0] - origin_point[0]).powi(2) + (point[1] - origin_point[1]).powi(2)).sqrt() * texel;
+            prop_assert!((across - (x * x + z * z).sqrt()).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn depth_bias_keeps_surfaces_out_of_their_own_shadow() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let settings = ShadowSettings { filter_radius: 0, ..ShadowSettings::default() };
+        let map = half_covered(settings);
+        // The occluder's own top is lit, the ground below it is not, and uncovered ground is lit
+        assert_eq!(map.get_visibility(Point3::new(-1.0, 1.0, 0.0), up, up), 1.0);
+        assert_eq!(map.get_visibility(Point3::new(-1.0, 0.99, 0.0), up, up), 1.0);
+        assert_eq!(map.get_visibility(Point3::new(-1.0, -1.0, 0.0), up, up), 0.0);
+        assert_eq!(map.get_visibility(Point3::new(1.0, -1.0, 0.0), up, up), 1.0);
+
+        let unbiased = half_covered(ShadowSettings { depth_bias: 0.0, slope_bias: 0.0, ..settings });
+        assert_eq!(unbiased.get_visibility(Point3::new(-1.0, 0.99, 0.0), up, up), 0.0);
+    }
+
+    #[test]
+    fn filtering_softens_shadow_edges() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let hard = half_covered(ShadowSettings { filter_radius: 0, ..ShadowSettings::default() });
+        let soft = half_covered(ShadowSettings { filter_radius: 2, ..ShadowSettings::default() });
+        let edge = Point3::new(0.0, -1.0, 0.0);
+        assert!(hard.get_visibility(edge, up, up) == 0.0 || hard.get_visibility(edge, up, up) == 1.0);
+        let visibility = soft.get_visibility(edge, up, up);
+        assert!(visibility > 0.0 && visibility < 1.0);
+
+        // Away from the edge the filter sees only one side
+        assert_eq!(soft.get_visibility(Point3::new(-1.0, -1.0, 0.0), up, up), 0.0);
+        assert_eq!(soft.get_visibility(Point3::new(1.0, -1.0, 0.0), up, up), 1.0);
+    }
+
+    #[test]
+    fn only_directional_and_spot_lights_cast_maps() {
+        let bounds = [Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0)];
+        let light = |mode| Light { mode, intensity: 1.0, position: Point3::new(0.0, 3.0, 0.0), direction: Vec3::new(0.0, -1.0, 0.0) };
+        assert!(ShadowMap::new(&light(LightMode::Ambient), bounds, ShadowSettings::default()).is_none());
+        assert!(ShadowMap::new(&light(LightMode::Point), bounds, ShadowSettings::default()).is_none());
+
+        let spot = ShadowMap::new(&light(LightMode::Spot(30.0)), bounds, ShadowSettings::default()).unwrap();
+        assert!(spot.is_perspective());
+        // Straight below the spot lands in the middle of its map, three units away
+        let (point, depth) = spot.project(Point3::ORIGIN).unwrap();
+        assert!(point[0].abs() < 1e-3 && point[1].abs() < 1e-3 && (depth - 3.0).abs() < 1e-4);
+        assert!(spot.project(Point3::new(0.0, 4.0, 0.0)).is_none());
+        assert!(light(LightMode::Spot(30.0)).reaches(Point3::new(1.0, 0.0, 0.0)));
+        assert!(!light(LightMode::Spot(30.0)).reaches(Point3::new(2.0, 0.0, 0.0)));
+    }
+}
@@ -0,0 +1,397 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::math::Color;
+
+#[derive(Clone, Copy)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    Trilinear,
+}
+
+#[derive(Clone, Copy)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+}
+
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+}
+
+impl MipLevel {
+    fn downsample(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut texels = Vec::with_capacity(width * height);
+
+        // Box filter over the 2x2 parent texels, clamped for odd or single-texel dimensions
+        for y in 0..height {
+            for x in 0..width {
+                let x0 = (2 * x).min(self.width - 1);
+                let x1 = (2 * x + 1).min(self.width - 1);
+                let y0 = (2 * y).min(self.height - 1);
+                let y1 = (2 * y + 1).min(self.height - 1);
+                let mut texel = Color::BLACK;
+                for (tx, ty) in [(x0, y0), (x1, y0), (x0, y1), (x1, y1)] {
+                    texel += self.texels[ty * self.width + tx];
+                }
+                texels.push(texel / 4.0);
+            }
+        }
+
+        return Self { width, height, texels };
+    }
+
+    fn get_texel(&self, x: i32, y: i32, wrap: WrapMode) -> Color {
+        let (x, y) = match wrap {
+            WrapMode::Repeat => (
+                x.rem_euclid(self.width as i32),
+                y.rem_euclid(self.height as i32),
+            ),
+            WrapMode::Clamp => (
+                x.clamp(0, self.width as i32 - 1),
+                y.clamp(0, self.height as i32 - 1),
+            ),
+        };
+        return self.texels[y as usize * self.width + x as usize];
+    }
+
+    fn sample_nearest(&self, uv: [f32; 2], wrap: WrapMode) -> Color {
+        let x = (uv[0] * self.width as f32).floor() as i32;
+        let y = (uv[1] * self.height as f32).floor() as i32;
+        return self.get_texel(x, y, wrap);
+    }
+
+    fn sample_bilinear(&self, uv: [f32; 2], wrap: WrapMode) -> Color {
+        // Texel centers sit at half-integer coordinates
+        let x = uv[0] * self.width as f32 - 0.5;
+        let y = uv[1] * self.height as f32 - 0.5;
+        let x_floor = x.floor();
+        let y_floor = y.floor();
+        let x_fraction = x - x_floor;
+        let y_fraction = y - y_floor;
+        let (x0, y0) = (x_floor as i32, y_floor as i32);
+
+        let top_left = self.get_texel(x0, y0, wrap);
+        let top_right = self.get_texel(x0 + 1, y0, wrap);
+        let bottom_left = self.get_texel(x0, y0 + 1, wrap);
+        let bottom_right = self.get_texel(x0 + 1, y0 + 1, wrap);
+
+        let top = top_left.lerp(top_right, x_fraction);
+        let bottom = bottom_left.lerp(bottom_right, x_fraction);
+        return top.lerp(bottom, y_fraction);
+    }
+}
+
+pub struct Texture {
+    levels: Vec<MipLevel>,
+    filter: Filter,
+    wrap: WrapMode,
+}
+
+impl Texture {
+    pub fn new(width: usize, height: usize, texels: Vec<Color>) -> Self {
+        assert_eq!(texels.len(), width * height, "texel count does not match texture size");
+        let mut levels = vec![MipLevel { width, height, texels }];
+        while let Some(last) = levels.last().filter(|level| level.width > 1 || level.height > 1) {
+            let next = last.downsample();
+            levels.push(next);
+        }
+        return Self { levels, filter: Filter::Bilinear, wrap: WrapMode::Repeat };
+    }
+
+    pub fn checkerboard(size: usize, cells: usize, color_a: Color, color_b: Color) -> Self {
+        let cell_size = (size / cells).max(1);
+        let mut texels = Vec::with_capacity(size * size);
+        for y in 0..size {
+            for x in 0..size {
+                let even = (x / cell_size + y / cell_size).is_multiple_of(2);
+                texels.push(if even { color_a } else { color_b });
+            }
+        }
+        return Self::new(size, size, texels);
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        let extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        return match extension.as_deref() {
+            Some("png") => Self::from_png(&bytes),
+            Some("ppm") => Self::from_ppm(&bytes),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported texture format")),
+        }
+    }
+
+    pub fn from_png(bytes: &[u8]) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            png::ColorType::Indexed => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "indexed png was not expanded"));
+            }
+        };
+
+        let texel_count = Self::check_size(info.width as usize, info.height as usize)?;
+        let texels: Vec<Color> = buffer[..info.buffer_size()]
+            .chunks_exact(channels)
+            .map(|pixel| match channels {
+                1 | 2 => Color::new(pixel[0] as f32, pixel[0] as f32, pixel[0] as f32),
+                _ => Color::new(pixel[0] as f32, pixel[1] as f32, pixel[2] as f32),
+            })
+            .collect();
+        if texels.len() != texel_count {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "png: decoded size does not match the header"));
+        }
+
+        return Ok(Self::new(info.width as usize, info.height as usize, texels));
+    }
+
+    pub fn from_ppm(bytes: &[u8]) -> io::Result<Self> {
+        fn invalid(message: &str) -> io::Error {
+            return io::Error::new(io::ErrorKind::InvalidData, format!("ppm: {}", message));
+        }
+
+        // Header tokens are whitespace separated and may be interleaved with '#' comments
+        let mut cursor = 0;
+        let mut next_token = || -> io::Result<String> {
+            loop {
+                while cursor < bytes.len() && bytes[cursor].is_ascii_whitespace() { cursor += 1; }
+                if cursor < bytes.len() && bytes[cursor] == b'#' {
+                    while cursor < bytes.len() && bytes[cursor] != b'\n' { cursor += 1; }
+                    continue;
+                }
+                break;
+            }
+            let start = cursor;
+            while cursor < bytes.len() && !bytes[cursor].is_ascii_whitespace() { cursor += 1; }
+            if start == cursor { return Err(invalid("unexpected end of file")); }
+            return Ok(String::from_utf8_lossy(&bytes[start..cursor]).into_owned());
+        };
+
+        let magic = next_token()?;
+        let mut number = || -> io::Result<usize> {
+            return next_token()?.parse().map_err(|_| invalid("malformed header"));
+        };
+        let width = number()?;
+        let height = number()?;
+        let max_value = number()?;
+        if max_value == 0 || max_value > 255 { return Err(invalid("only 8-bit images are supported")); }
+        let scale = 255.0 / max_value as f32;
+        let sample_count = Self::check_size(width, height)?
+            .checked_mul(3)
+            .ok_or_else(|| invalid("image is too large"))?;
+
+        let samples: Vec<f32> = match magic.as_str() {
+            "P3" => {
+                let mut samples = Vec::with_capacity(sample_count.min(bytes.len()));
+                for _ in 0..sample_count {
+                    samples.push(number()? as f32 * scale);
+                }
+                samples
+            }
+            "P6" => {
+                // Exactly one whitespace byte separates the header from the binary raster
+                let start = cursor + 1;
+                let end = start.saturating_add(sample_count);
+                if end > bytes.len() { return Err(invalid("truncated raster")); }
+                bytes[start..end].iter().map(|&sample| sample as f32 * scale).collect()
+            }
+            _ => return Err(invalid("expected P3 or P6 magic number")),
+        };
+
+        let texels = samples
+            .chunks_exact(3)
+            .map(|pixel| Color::new(pixel[0], pixel[1], pixel[2]))
+            .collect();
+
+        return Ok(Self::new(width, height, texels));
+    }
+
+    // Texel count of an image, loaded images must have at least one texel to sample and downsample
+    fn check_size(width: usize, height: usize) -> io::Result<usize> {
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("image is {}x{} texels", width, height)));
+        }
+        return width.checked_mul(height).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "image is too large"));
+    }
+
+    pub fn set_sampling(&mut self, filter: Filter, wrap: WrapMode) {
+        self.filter = filter;
+        self.wrap = wrap;
+    }
+
+    pub fn get_size(&self) -> [usize; 2] {
+        return [self.levels[0].width, self.levels[0].height];
+    }
+
+    pub fn get_mip_count(&self) -> usize {
+        return self.levels.len();
+    }
+
+    // Level of detail from the UV change over one pixel step in x and y
+    pub fn get_lod(&self, uv_dx: [f32; 2], uv_dy: [f32; 2]) -> f32 {
+        let [width, height] = self.get_size();
+        let texels_dx = (uv_dx[0] * width as f32).hypot(uv_dx[1] * height as f32);
+        let texels_dy = (uv_dy[0] * width as f32).hypot(uv_dy[1] * height as f32);
+        let footprint = texels_dx.max(texels_dy);
+
+        return if footprint.is_finite() && footprint > 1.0 { footprint.log2() } else { 0.0 };
+    }
+
+    pub fn sample(&self, uv: [f32; 2]) -> Color {
+        return self.sample_lod(uv, 0.0);
+    }
+
+    pub fn sample_lod(&self, uv: [f32; 2], lod: f32) -> Color {
+        return match self.filter {
+            Filter::Nearest => self.levels[0].sample_nearest(uv, self.wrap),
+            Filter::Bilinear => self.levels[0].sample_bilinear(uv, self.wrap),
+            Filter::Trilinear => self.sample_trilinear(uv, lod),
+        }
+    }
+
+    fn sample_trilinear(&self, uv: [f32; 2], lod: f32) -> Color {
+        let lod = lod.clamp(0.0, (self.levels.len() - 1) as f32);
+        let lower = lod.floor() as usize;
+        let upper = (lower + 1).min(self.levels.len() - 1);
+        let fraction = lod - lower as f32;
+
+        let near = self.levels[lower].sample_bilinear(uv, self.wrap);
+        if upper == lower { return near; }
+        let far = self.levels[upper].sample_bilinear(uv, self.wrap);
+
+        return near.lerp(far, fraction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::Frame;
+
+    // Black on the left half of every row and white on the right
+    fn halves(width: usize, height: usize) -> Texture {
+        let texels = (0..width * height).map(|index| if index % width < width / 2 { Color::BLACK } else { Color::WHITE }).collect();
+        return Texture::new(width, height, texels);
+    }
+
+    fn assert_invalid(result: io::Result<Texture>) {
+        match result {
+            Ok(_) => panic!("malformed image was loaded"),
+            Err(error) => assert_eq!(error.kind(), io::ErrorKind::InvalidData),
+        }
+    }
+
+    #[test]
+    fn ppm_loads_ascii_and_binary_rasters() {
+        let ascii = Texture::from_ppm(b"P3\n# two texels\n2 1\n15\n15 0 0  0 5 15\n").unwrap();
+        assert_eq!(ascii.get_size(), [2, 1]);
+        ascii.levels[0].texels.iter().zip([Color::new(255.0, 0.0, 0.0), Color::new(0.0, 85.0, 255.0)]).for_each(|(&texel, expected)| {
+            assert_eq!(texel, expected);
+        });
+
+        let mut binary = b"P6 1 2 255\n".to_vec();
+        binary.extend_from_slice(&[10, 20, 30, 40, 50, 60]);
+        let binary = Texture::from_ppm(&binary).unwrap();
+        assert_eq!(binary.get_size(), [1, 2]);
+        assert_eq!(binary.levels[0].texels[1], Color::new(40.0, 50.0, 60.0));
+    }
+
+    #[test]
+    fn malformed_ppms_are_errors() {
+        assert_invalid(Texture::from_ppm(b"P3\n0 4\n255\n"));
+        assert_invalid(Texture::from_ppm(b"P6\n4 0\n255\n"));
+        assert_invalid(Texture::from_ppm(b"P3\n2 2\n255\n1 2 3\n"));
+        assert_invalid(Texture::from_ppm(b"P6\n2 2\n255\n\x01\x02"));
+        assert_invalid(Texture::from_ppm(b"P6\n99999999999 99999999999\n255\n"));
+        assert_invalid(Texture::from_ppm(b"P6\n-1 2\n255\n"));
+        assert_invalid(Texture::from_ppm(b"P6\n1 1\n65535\n\x00\x00\x00\x00\x00\x00"));
+        assert_invalid(Texture::from_ppm(b"P5\n1 1\n255\n\x00"));
+        assert_invalid(Texture::from_ppm(b""));
+    }
+
+    #[test]
+    fn load_picks_the_decoder_from_the_extension() {
+        let directory = std::env::temp_dir().join(format!("cgfs-texture-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let mut frame = Frame::new(3, 2, Color::BLACK);
+        frame.set_pixel(2, 1, [200, 100, 50]);
+        let png_path = directory.join("frame.PNG");
+        frame.save_png(&png_path).unwrap();
+        let texture = Texture::load(&png_path).unwrap();
+        assert_eq!(texture.get_size(), [3, 2]);
+        assert_eq!(texture.levels[0].texels[5], Color::new(200.0, 100.0, 50.0));
+
+        let ppm_path = directory.join("texel.ppm");
+        fs::write(&ppm_path, b"P3 1 1 255 7 8 9").unwrap();
+        assert_eq!(Texture::load(&ppm_path).unwrap().levels[0].texels[0], Color::new(7.0, 8.0, 9.0));
+
+        let bmp_path = directory.join("texel.bmp");
+        fs::write(&bmp_path, b"BM").unwrap();
+        assert_eq!(Texture::load(&bmp_path).err().map(|error| error.kind()), Some(io::ErrorKind::InvalidInput));
+        assert_eq!(Texture::load(directory.join("missing.png")).err().map(|error| error.kind()), Some(io::ErrorKind::NotFound));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn mip_levels_halve_down_to_one_texel() {
+        let texture = halves(8, 2);
+        let sizes: Vec<[usize; 2]> = texture.levels.iter().map(|level| [level.width, level.height]).collect();
+        assert_eq!(sizes, vec![[8, 2], [4, 1], [2, 1], [1, 1]]);
+        assert_eq!(texture.get_mip_count(), 4);
+
+        // The last level averages the whole image
+        let average = texture.levels[3].texels[0];
+        assert!((average.r - 127.5).abs() < 1e-3 && (average.g - 127.5).abs() < 1e-3, "{:?}", average);
+
+        assert_eq!(Texture::new(1, 1, vec![Color::WHITE]).get_mip_count(), 1);
+    }
+
+    #[test]
+    fn one_texel_footprint_is_lod_zero() {
+        let texture = halves(64, 64);
+        let texel = 1.0 / 64.0;
+        assert_eq!(texture.get_lod([texel, 0.0], [0.0, texel]), 0.0);
+        assert_eq!(texture.get_lod([texel / 4.0, 0.0], [0.0, texel / 4.0]), 0.0);
+        assert!((texture.get_lod([4.0 * texel, 0.0], [0.0, texel]) - 2.0).abs() < 1e-5);
+        assert_eq!(texture.get_lod([f32::NAN, 0.0], [0.0, texel]), 0.0);
+    }
+
+    #[test]
+    fn trilinear_blends_between_levels() {
+        let mut texture = halves(4, 4);
+        texture.set_sampling(Filter::Trilinear, WrapMode::Clamp);
+
+        // A texel center on the black side is black at level 0 and grey once the halves are averaged at level 2
+        let uv = [0.125, 0.5];
+        let base = texture.sample_lod(uv, 0.0);
+        let coarsest = texture.sample_lod(uv, 2.0);
+        let between = texture.sample_lod(uv, 1.5);
+        assert_eq!(base, Color::BLACK);
+        assert!((coarsest.r - 127.5).abs() < 1e-3, "{:?}", coarsest);
+        let level_1 = texture.levels[1].sample_bilinear(uv, WrapMode::Clamp);
+        assert!((between.r - (level_1.r + coarsest.r) / 2.0).abs() < 1e-3, "{:?} {:?}", between, level_1);
+        assert!(between.r > base.r && between.r < 255.0);
+
+        // Past the last level the sample stays on it
+        assert_eq!(texture.sample_lod(uv, 10.0), coarsest);
+    }
+}
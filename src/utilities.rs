@@ -1,4 +1,4 @@
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub enum Axis { X, Y, Z }
 
 pub fn dot_product(v1: [f32; 3], v2: [f32; 3]) -> f32 {
@@ -46,7 +46,7 @@ pub fn multiply_color(color: [f32; 3], factor: f32) -> [f32; 3] {
 }
 
 pub fn interpolate(start_idx: i32, start_val: f32, end_idx: i32, end_val: f32) -> Vec<i32> {
-    if start_idx == end_idx { return vec![start_val as i32]; }
+    if start_idx == end_idx { return vec![start_val.round() as i32]; }
 
     let mut values = Vec::new();
     let step = (end_val - start_val) / (end_idx - start_idx) as f32;
@@ -60,32 +60,32 @@ pub fn interpolate(start_idx: i32, start_val: f32, end_idx: i32, end_val: f32) -
     return values;
 }
 
-pub fn rotate_vector(v1: [f32; 3], rotation: &(Axis, f32)) -> [f32; 3] {
-    let (axis, angle) = rotation;
-    let radian = angle.to_radians();
-
+pub fn axis_direction(axis: Axis) -> [f32; 3] {
     return match axis {
-        Axis::X => {
-            let x = v1[0];
-            let y = v1[1] * radian.cos() - v1[2] * radian.sin();
-            let z = v1[1] * radian.sin() + v1[2] * radian.cos();
-            [x, y, z]
-        },
-        Axis::Y => {
-            let x = v1[2] * radian.sin() + v1[0] * radian.cos();
-            let y = v1[1];
-            let z = v1[2] * radian.cos() - v1[0] * radian.sin();
-            [x, y, z]
-        },
-        Axis::Z => {
-            let x = v1[0] * radian.cos() - v1[1] * radian.sin();
-            let y = v1[0] * radian.sin() + v1[1] * radian.cos();
-            let z = v1[2];
-            [x, y, z]
-        },
+        Axis::X => [1.0, 0.0, 0.0],
+        Axis::Y => [0.0, 1.0, 0.0],
+        Axis::Z => [0.0, 0.0, 1.0],
     }
 }
 
+// Rodrigues' rotation of a vector by an angle in degrees around an arbitrary axis through the origin,
+// the coordinate axes come from `axis_direction`
+pub fn rotate_vector(v1: [f32; 3], axis: [f32; 3], angle: f32) -> [f32; 3] {
+    let length = vector_length(axis);
+    if length == 0.0 { return v1; }
+
+    let k = divide_vector(axis, length);
+    let radian = angle.to_radians();
+    let cross = [
+        k[1] * v1[2] - k[2] * v1[1],
+        k[2] * v1[0] - k[0] * v1[2],
+        k[0] * v1[1] - k[1] * v1[0],
+    ];
+
+    let parallel = scale_vector(k, dot_product(k, v1) * (1.0 - radian.cos()));
+    return vector_addition(vector_addition(scale_vector(v1, radian.cos()), scale_vector(cross, radian.sin())), parallel);
+}
+
 pub fn mat4_default() -> [[f32; 4]; 4] {
     return [
         [1.0, 0.0, 0.0, 0.0],
@@ -187,6 +187,22 @@ pub fn to_inverse_rotation_mat4(axis: Axis, radian: f32) -> [[f32; 4]; 4] {
     }
 }
 
+// Matrix form of Rodrigues' rotation, cos * I + sin * K + (1 - cos) * k k^T for the unit axis k
+pub fn to_axis_rotation_mat4(axis: [f32; 3], radian: f32) -> [[f32; 4]; 4] {
+    let length = vector_length(axis);
+    if length == 0.0 { return mat4_default(); }
+
+    let [x, y, z] = divide_vector(axis, length);
+    let (sin, cos) = radian.sin_cos();
+    let t = 1.0 - cos;
+    return [
+        [cos + x * x * t,     x * y * t - z * sin, x * z * t + y * sin, 0.0],
+        [y * x * t + z * sin, cos + y * y * t,     y * z * t - x * sin, 0.0],
+        [z * x * t - y * sin, z * y * t + x * sin, cos + z * z * t,     0.0],
+        [0.0,                 0.0,                 0.0,                 1.0],
+    ];
+}
+
 pub fn multiply_mat4_vec(mat4: [[f32; 4]; 4], vec: [f32; 4]) -> [f32; 4] {
     return [
         mat4[0][0] * vec[0] + mat4[0][1] * vec[1] + mat4[0][2] * vec[2] + mat4[0][3] * vec[3],
@@ -210,3 +226,287 @@ pub fn multiply_mat4_mat4(mat_a: [[f32; 4]; 4], mat_b: [[f32; 4]; 4]) -> [[f32;
 
     return result;
 }
+
+pub fn transpose_mat4(mat4: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut result = [[0f32; 4]; 4];
+
+    for i in 0..4 {
+        for j in 0..4 {
+            result[j][i] = mat4[i][j];
+        }
+    }
+
+    return result;
+}
+
+// 2x2 minors of the top two and bottom two rows, shared by the determinant and the inverse
+fn mat4_minors(m: [[f32; 4]; 4]) -> ([f32; 6], [f32; 6]) {
+    let top = [
+        m[0][0] * m[1][1] - m[0][1] * m[1][0],
+        m[0][0] * m[1][2] - m[0][2] * m[1][0],
+        m[0][0] * m[1][3] - m[0][3] * m[1][0],
+        m[0][1] * m[1][2] - m[0][2] * m[1][1],
+        m[0][1] * m[1][3] - m[0][3] * m[1][1],
+        m[0][2] * m[1][3] - m[0][3] * m[1][2],
+    ];
+    let bottom = [
+        m[2][0] * m[3][1] - m[2][1] * m[3][0],
+        m[2][0] * m[3][2] - m[2][2] * m[3][0],
+        m[2][0] * m[3][3] - m[2][3] * m[3][0],
+        m[2][1] * m[3][2] - m[2][2] * m[3][1],
+        m[2][1] * m[3][3] - m[2][3] * m[3][1],
+        m[2][2] * m[3][3] - m[2][3] * m[3][2],
+    ];
+    return (top, bottom);
+}
+
+pub fn determinant_mat4(mat4: [[f32; 4]; 4]) -> f32 {
+    let (s, c) = mat4_minors(mat4);
+    return s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+}
+
+fn row_lengths_product(mat4: [[f32; 4]; 4]) -> f32 {
+    return mat4.iter()
+        .map(|row| row.iter().map(|value| value * value).sum::<f32>().sqrt())
+        .product();
+}
+
+// Returns None when the matrix is singular. The determinant is compared against the smaller of the
+// row and column length products, which bound it, so the check does not depend on the overall scale
+// of the matrix and a large translation column does not hide a well-conditioned rotation and scale
+pub fn inverse_mat4(mat4: [[f32; 4]; 4]) -> Option<[[f32; 4]; 4]> {
+    const SINGULAR_EPSILON: f32 = 1e-6;
+
+    let m = mat4;
+    let (s, c) = mat4_minors(m);
+    let determinant = determinant_mat4(m);
+    let bound = row_lengths_product(m).min(row_lengths_product(transpose_mat4(m)));
+    if !determinant.is_finite() || determinant.abs() <= SINGULAR_EPSILON * bound {
+        return None;
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let adjugate = [
+        [
+             m[1][1] * c[5] - m[1][2] * c[4] + m[1][3] * c[3],
+            -m[0][1] * c[5] + m[0][2] * c[4] - m[0][3] * c[3],
+             m[3][1] * s[5] - m[3][2] * s[4] + m[3][3] * s[3],
+            -m[2][1] * s[5] + m[2][2] * s[4] - m[2][3] * s[3],
+        ],
+        [
+            -m[1][0] * c[5] + m[1][2] * c[2] - m[1][3] * c[1],
+             m[0][0] * c[5] - m[0][2] * c[2] + m[0][3] * c[1],
+            -m[3][0] * s[5] + m[3][2] * s[2] - m[3][3] * s[1],
+             m[2][0] * s[5] - m[2][2] * s[2] + m[2][3] * s[1],
+        ],
+        [
+             m[1][0] * c[4] - m[1][1] * c[2] + m[1][3] * c[0],
+            -m[0][0] * c[4] + m[0][1] * c[2] - m[0][3] * c[0],
+             m[3][0] * s[4] - m[3][1] * s[2] + m[3][3] * s[0],
+            -m[2][0] * s[4] + m[2][1] * s[2] - m[2][3] * s[0],
+        ],
+        [
+            -m[1][0] * c[3] + m[1][1] * c[1] - m[1][2] * c[0],
+             m[0][0] * c[3] - m[0][1] * c[1] + m[0][2] * c[0],
+            -m[3][0] * s[3] + m[3][1] * s[1] - m[3][2] * s[0],
+             m[2][0] * s[3] - m[2][1] * s[1] + m[2][2] * s[0],
+        ],
+    ];
+
+    return Some(adjugate.map(|row| row.map(|value| value * inverse_determinant)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn close(a: f32, b: f32, tolerance: f32) -> bool {
+        (a - b).abs() <= tolerance * (1.0 + a.abs().max(b.abs()))
+    }
+
+    fn vectors_close(a: [f32; 3], b: [f32; 3]) -> bool {
+        (0..3).all(|i| close(a[i], b[i], EPSILON))
+    }
+
+    fn mat4_close(a: [[f32; 4]; 4], b: [[f32; 4]; 4], tolerance: f32) -> bool {
+        (0..4).all(|i| (0..4).all(|j| close(a[i][j], b[i][j], tolerance)))
+    }
+
+    fn axis() -> impl Strategy<Value = Axis> {
+        prop_oneof![Just(Axis::X), Just(Axis::Y), Just(Axis::Z)]
+    }
+
+    fn vector() -> impl Strategy<Value = [f32; 3]> {
+        [-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0]
+    }
+
+    fn unit_axis() -> impl Strategy<Value = [f32; 3]> {
+        vector().prop_filter("axis needs a direction", |axis| vector_length(*axis) > 1e-2)
+    }
+
+    fn angle() -> impl Strategy<Value = f32> {
+        -360.0f32..360.0
+    }
+
+    fn scale() -> impl Strategy<Value = f32> {
+        prop_oneof![0.2f32..5.0, -5.0f32..-0.2]
+    }
+
+    // Scaled, rotated and translated like the model and camera matrices, so it is always invertible
+    fn affine_mat4() -> impl Strategy<Value = [[f32; 4]; 4]> {
+        (unit_axis(), angle(), [scale(), scale(), scale()], vector()).prop_map(|(axis, angle, scale, translation)| {
+            let rotation = to_axis_rotation_mat4(axis, angle.to_radians());
+            let model = multiply_mat4_mat4(rotation, to_scale_mat4(scale));
+            multiply_mat4_mat4(to_translation_mat4(translation), model)
+        })
+    }
+
+    fn extend(vector: [f32; 3], w: f32) -> [f32; 4] {
+        [vector[0], vector[1], vector[2], w]
+    }
+
+    fn truncate(vector: [f32; 4]) -> [f32; 3] {
+        [vector[0], vector[1], vector[2]]
+    }
+
+    proptest! {
+        #[test]
+        fn addition_and_subtraction_cancel(a in vector(), b in vector()) {
+            prop_assert!(vectors_close(vector_subtraction(vector_addition(a, b), b), a));
+        }
+
+        #[test]
+        fn dot_product_is_symmetric_and_gives_length(a in vector(), b in vector()) {
+            prop_assert_eq!(dot_product(a, b), dot_product(b, a));
+            prop_assert!(close(dot_product(a, a).sqrt(), vector_length(a), EPSILON));
+        }
+
+        #[test]
+        fn scaling_scales_length(v in vector(), factor in -10.0f32..10.0) {
+            prop_assert!(close(vector_length(scale_vector(v, factor)), factor.abs() * vector_length(v), EPSILON));
+        }
+
+        #[test]
+        fn divide_undoes_scale(v in vector(), factor in scale()) {
+            prop_assert!(vectors_close(divide_vector(scale_vector(v, factor), factor), v));
+        }
+
+        #[test]
+        fn reverse_is_negative_scale(v in vector()) {
+            prop_assert_eq!(reverse_vector(v), scale_vector(v, -1.0));
+            prop_assert_eq!(vector_addition(v, reverse_vector(v)), [0.0; 3]);
+        }
+
+        #[test]
+        fn multiply_is_componentwise(a in vector(), b in vector()) {
+            let product = vector_multiplication(a, b);
+            prop_assert_eq!(product, [a[0] * b[0], a[1] * b[1], a[2] * b[2]]);
+        }
+
+        #[test]
+        fn color_to_vector_keeps_channels(color in [0i32..=255, 0i32..=255, 0i32..=255]) {
+            let vector = color_to_vector(color);
+            prop_assert_eq!(vector.map(|channel| channel as i32), color);
+        }
+
+        #[test]
+        fn multiply_color_stays_in_range(color in [0.0f32..=255.0, 0.0f32..=255.0, 0.0f32..=255.0], factor in -2.0f32..4.0) {
+            let result = multiply_color(color, factor);
+            prop_assert!(result.iter().all(|channel| (0.0..=255.0).contains(channel)));
+        }
+
+        #[test]
+        fn interpolate_hits_endpoints(start_idx in -500i32..500, length in 0i32..500, start_val in -1000.0f32..1000.0, end_val in -1000.0f32..1000.0) {
+            let end_idx = start_idx + length;
+            let values = interpolate(start_idx, start_val, end_idx, end_val);
+
+            prop_assert_eq!(values.len(), length as usize + 1);
+            prop_assert_eq!(values[0], start_val.round() as i32);
+            // Steps are accumulated, so the last value may round to the neighbour of the end value
+            prop_assert!(length == 0 || (*values.last().unwrap() as f32 - end_val).abs() <= 0.75);
+        }
+
+        #[test]
+        fn rotate_vector_around_coordinate_axis_matches_rotation_mat4(v in vector(), axis in axis(), angle in angle()) {
+            let rotated = rotate_vector(v, axis_direction(axis), angle);
+            let matrix = to_rotation_mat4(axis, angle.to_radians());
+            prop_assert!(vectors_close(rotated, truncate(multiply_mat4_vec(matrix, extend(v, 0.0)))));
+            prop_assert!(close(vector_length(rotated), vector_length(v), EPSILON));
+            prop_assert!(mat4_close(to_axis_rotation_mat4(axis_direction(axis), angle.to_radians()), matrix, EPSILON));
+        }
+
+        #[test]
+        fn rotate_vector_matches_axis_rotation_mat4(v in vector(), axis in unit_axis(), angle in angle()) {
+            let rotated = rotate_vector(v, axis, angle);
+            let matrix = to_axis_rotation_mat4(axis, angle.to_radians());
+            prop_assert!(vectors_close(rotated, truncate(multiply_mat4_vec(matrix, extend(v, 0.0)))));
+            prop_assert!(close(vector_length(rotated), vector_length(v), EPSILON));
+        }
+
+        #[test]
+        fn inverse_rotation_undoes_rotation(axis in axis(), angle in angle()) {
+            let radian = angle.to_radians();
+            let product = multiply_mat4_mat4(to_rotation_mat4(axis, radian), to_inverse_rotation_mat4(axis, radian));
+            prop_assert!(mat4_close(product, mat4_default(), EPSILON));
+            prop_assert!(mat4_close(to_inverse_rotation_mat4(axis, radian), transpose_mat4(to_rotation_mat4(axis, radian)), EPSILON));
+            prop_assert!(close(determinant_mat4(to_rotation_mat4(axis, radian)), 1.0, EPSILON));
+        }
+
+        #[test]
+        fn inverse_translation_undoes_translation(translation in vector(), point in vector()) {
+            let moved = multiply_mat4_vec(to_translation_mat4(translation), extend(point, 1.0));
+            let back = multiply_mat4_vec(to_inverse_translation_mat4(translation), moved);
+            prop_assert!(vectors_close(truncate(back), point));
+        }
+
+        #[test]
+        fn identity_is_neutral(m in affine_mat4()) {
+            prop_assert_eq!(multiply_mat4_mat4(m, mat4_default()), m);
+            prop_assert_eq!(multiply_mat4_mat4(mat4_default(), m), m);
+        }
+
+        #[test]
+        fn multiply_is_associative(a in affine_mat4(), b in affine_mat4(), v in vector()) {
+            let combined = multiply_mat4_vec(multiply_mat4_mat4(a, b), extend(v, 1.0));
+            let in_turn = multiply_mat4_vec(a, multiply_mat4_vec(b, extend(v, 1.0)));
+            prop_assert!((0..4).all(|i| close(combined[i], in_turn[i], 1e-3)));
+        }
+
+        #[test]
+        fn transpose_twice_is_identity(m in affine_mat4()) {
+            prop_assert_eq!(transpose_mat4(transpose_mat4(m)), m);
+        }
+
+        #[test]
+        fn determinant_of_product_is_product_of_determinants(a in affine_mat4(), b in affine_mat4()) {
+            let product = determinant_mat4(multiply_mat4_mat4(a, b));
+            prop_assert!(close(product, determinant_mat4(a) * determinant_mat4(b), 1e-3));
+        }
+
+        #[test]
+        fn inverse_gives_identity(m in affine_mat4()) {
+            let inverse = inverse_mat4(m).expect("affine matrices with non-zero scale are invertible");
+            prop_assert!(mat4_close(multiply_mat4_mat4(m, inverse), mat4_default(), 1e-3));
+            prop_assert!(mat4_close(multiply_mat4_mat4(inverse, m), mat4_default(), 1e-3));
+        }
+
+        #[test]
+        fn singular_matrix_has_no_inverse(m in affine_mat4(), row in 0usize..4, source in 0usize..4) {
+            let mut singular = m;
+            singular[row] = if row == source { [0.0; 4] } else { m[source] };
+            prop_assert!(inverse_mat4(singular).is_none());
+        }
+    }
+
+    // Scale 0.2 moved 100 units away has a determinant of 0.008, far below the product of its row lengths
+    #[test]
+    fn large_translations_stay_invertible() {
+        let model = multiply_mat4_mat4(to_translation_mat4([100.0, 100.0, 100.0]), to_scale_mat4([0.2, 0.2, 0.2]));
+        let inverse = inverse_mat4(model).expect("an affine matrix with a non-zero scale is invertible");
+        assert!(mat4_close(multiply_mat4_mat4(model, inverse), mat4_default(), 1e-4));
+        assert!(inverse_mat4(to_scale_mat4([0.2, 0.0, 0.2])).is_none());
+    }
+}
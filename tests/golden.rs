@@ -0,0 +1,118 @@
+// Renders the built-in scenes at a small size and compares them with the references in tests/golden.
+// Run with UPDATE_GOLDEN=1 to write new references after an intended change to the output.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use cgfs_with_wgpu::{Frame, RenderMode, Traversal, LineMode, init_raytracer_with_canvas, init_rasterizer_with_canvas};
+
+const CANVAS: [i32; 2] = [160, 160];
+// Largest channel difference a pixel may have before it counts as changed
+const TOLERANCE: u8 = 3;
+// Share of pixels allowed past the tolerance, absorbs float differences along edges
+const MAX_CHANGED_PIXELS: f64 = 0.005;
+const MIN_PSNR: f64 = 40.0;
+
+struct Comparison {
+    changed_pixels: usize,
+    psnr: f64,
+    diff: Frame,
+}
+
+// Changed pixels show in red over a dimmed copy of the reference
+fn compare(expected: &Frame, actual: &Frame) -> Comparison {
+    let [width, height] = expected.get_size();
+    let mut diff = Frame::new(width, height, cgfs_with_wgpu::BACKGROUND);
+    let mut changed_pixels = 0;
+    let mut squared_error = 0.0;
+
+    for row in 0..height {
+        for column in 0..width {
+            let expected_pixel = expected.get_pixel(column, row);
+            let actual_pixel = actual.get_pixel(column, row);
+            let differences = [0, 1, 2].map(|channel| expected_pixel[channel].abs_diff(actual_pixel[channel]));
+            squared_error += differences.iter().map(|&difference| (difference as f64).powi(2)).sum::<f64>();
+
+            let pixel = if differences.iter().any(|&difference| difference > TOLERANCE) {
+                changed_pixels += 1;
+                [255, 0, 0]
+            } else {
+                let gray = expected_pixel.iter().map(|&channel| channel as u16).sum::<u16>() / 3;
+                [(gray / 3) as u8; 3]
+            };
+            diff.set_pixel(column, row, pixel);
+        }
+    }
+
+    let mean_squared_error = squared_error / (width * height * 3) as f64;
+    let psnr = if mean_squared_error == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mean_squared_error).log10()
+    };
+
+    return Comparison { changed_pixels, psnr, diff };
+}
+
+fn assert_matches_golden(name: &str, actual: Frame) {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let reference_path = root.join("tests").join("golden").join(format!("{}.png", name));
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save_png(&reference_path).unwrap();
+        return;
+    }
+
+    let expected = Frame::load_png(&reference_path).unwrap_or_else(|e| {
+        panic!("could not read {}: {}, run with UPDATE_GOLDEN=1 to create it", reference_path.display(), e)
+    });
+    assert_eq!(expected.get_size(), actual.get_size(), "{} does not match the rendered size", reference_path.display());
+
+    let comparison = compare(&expected, &actual);
+    let [width, height] = actual.get_size();
+    let changed_share = comparison.changed_pixels as f64 / (width * height) as f64;
+    if changed_share <= MAX_CHANGED_PIXELS && comparison.psnr >= MIN_PSNR {
+        return;
+    }
+
+    let output = root.join("target").join("golden");
+    fs::create_dir_all(&output).unwrap();
+    let actual_path = output.join(format!("{}-actual.png", name));
+    let diff_path = output.join(format!("{}-diff.png", name));
+    actual.save_png(&actual_path).unwrap();
+    comparison.diff.save_png(&diff_path).unwrap();
+
+    panic!(
+        "{} differs from its reference: {} pixels ({:.2}%) past tolerance, PSNR {:.2} dB, see {} and {}",
+        name, comparison.changed_pixels, changed_share * 100.0, comparison.psnr, actual_path.display(), diff_path.display(),
+    );
+}
+
+#[test]
+fn raytracer_matches_golden() {
+    let raytracer = init_raytracer_with_canvas(CANVAS);
+    assert_matches_golden("raytracer", raytracer.get_frame());
+}
+
+#[test]
+fn rasterizer_matches_golden() {
+    let rasterizer = init_rasterizer_with_canvas(CANVAS);
+    assert_matches_golden("rasterizer", rasterizer.get_frame());
+}
+
+#[test]
+fn edge_function_rasterizer_matches_golden() {
+    let mut rasterizer = init_rasterizer_with_canvas(CANVAS);
+    rasterizer.set_traversal(Traversal::EdgeFunction);
+    rasterizer.pass();
+    assert_matches_golden("rasterizer-edge-function", rasterizer.get_frame());
+}
+
+#[test]
+fn antialiased_wireframe_matches_golden() {
+    let mut rasterizer = init_rasterizer_with_canvas(CANVAS);
+    rasterizer.set_mode(RenderMode::Wireframe);
+    rasterizer.set_line_mode(LineMode::AntiAliased);
+    rasterizer.set_line_width(1.5);
+    rasterizer.pass();
+    assert_matches_golden("wireframe-antialiased", rasterizer.get_frame());
+}